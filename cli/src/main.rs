@@ -1,15 +1,662 @@
-use db::{self, GetObservations};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{Parser, Subcommand};
+use core::{
+    queries::{ObservationRange, SortOrder},
+    rain::RainPeriods,
+    retention::RetentionPolicy,
+    rollup::{Rollup, RollupPeriod},
+    status::{find_reboots, Reboot, StatusReport},
+    units::{FormatWithUnits, UnitPreferences},
+    util::{format_duration, now_epoch},
+    weather::{Weather, WeatherMerger, WeatherReport},
+};
+use db::{
+    self, config::Config, Connection, DatabaseLocation, GetDeviceStatuses, GetHubStatuses,
+    GetLightningStrikes, GetObservations, GetPressureTrend, GetRainStarts, GetRainTotals,
+    GetRollups, Prune, RebuildRollups,
+};
+use std::{collections::BTreeMap, fmt, process, time::Duration as StdDuration};
+use watch::{watch_db, watch_udp, Printer};
 
-fn main() {
-    let conn = db::connect().unwrap();
+mod watch;
+
+#[derive(Parser)]
+#[command(about = "Show weather data recorded by the tempestrs listener")]
+struct Cli {
+    /// Database to read: a path, ":memory:", or the name of a database in the data directory.
+    /// Defaults to $TEMPESTRS_DB, then the config file, then "weather"
+    #[arg(long, global = true)]
+    db: Option<String>,
+    /// Units to show measurements in: "metric", "imperial", or kind=unit pairs such as
+    /// "metric,pressure=inhg" or "temperature=c,speed=kn". JSON output is converted too. Defaults
+    /// to the config file's units setting
+    #[arg(long, global = true)]
+    units: Option<UnitPreferences>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the latest observation (the default)
+    Latest {
+        /// Only show observations from the device with this serial number
+        #[arg(long)]
+        serial: Option<String>,
+        /// How many hours of observations the pressure trend is based on
+        #[arg(long, default_value_t = 3, value_parser = parse_hours)]
+        trend_hours: u64,
+    },
+    /// List observations over a span of time
+    History {
+        /// Start of the span, as a Unix epoch or a local "YYYY-MM-DD[ HH:MM]" time. Defaults to
+        /// 24 hours ago
+        #[arg(long, value_parser = parse_time)]
+        from: Option<u64>,
+        /// End of the span (exclusive), in the same formats as --from. Defaults to now
+        #[arg(long, value_parser = parse_time)]
+        to: Option<u64>,
+        /// Only show observations from the device with this serial number
+        #[arg(long)]
+        serial: Option<String>,
+        /// List oldest first instead of newest first
+        #[arg(long)]
+        asc: bool,
+        /// Show at most this many observations
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Skip this many observations before listing
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        /// Print observations as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show hourly or daily summaries
+    Rollups {
+        /// Show hourly summaries instead of daily ones
+        #[arg(long)]
+        hourly: bool,
+        /// How many days back to look
+        #[arg(long, default_value_t = 7, value_parser = parse_days)]
+        days: u64,
+        /// Only show summaries for the device with this serial number
+        #[arg(long)]
+        serial: Option<String>,
+        /// Recompute every summary from the stored observations first
+        #[arg(long)]
+        rebuild: bool,
+        /// Print summaries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Delete data older than the retention policy allows
+    Prune {
+        /// Retention policy as kind=days pairs, e.g. "observation=30,hourly=730,daily=forever".
+        /// Kinds left out keep their defaults
+        #[arg(long, default_value_t = RetentionPolicy::default())]
+        policy: RetentionPolicy,
+        /// Report what would be deleted without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List lightning strikes
+    Strikes {
+        /// How many hours back to look
+        #[arg(long, default_value_t = 24, value_parser = parse_hours)]
+        hours: u64,
+    },
+    /// Show the current rain rate and rain totals
+    Rain {
+        /// Only count rain measured by the device with this serial number. Defaults to the station
+        /// that reported the latest observation
+        #[arg(long)]
+        serial: Option<String>,
+        /// The local hour the rain day starts at, e.g. 9 for a 9 AM reset. Defaults to the
+        /// station's rain_day_start setting, or midnight
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..24))]
+        day_start: Option<u32>,
+        /// Print the totals as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List rain onsets
+    RainStarts {
+        /// How many hours back to look
+        #[arg(long, default_value_t = 24, value_parser = parse_hours)]
+        hours: u64,
+    },
+    /// Show signal strength, battery and uptime history for devices and hubs
+    Status {
+        /// How many hours back to look
+        #[arg(long, default_value_t = 24, value_parser = parse_hours)]
+        hours: u64,
+    },
+    /// Print observations, wind, strikes, rain and status reports as they arrive, one per line
+    Watch {
+        /// Listen for the hubs' UDP broadcasts directly instead of polling the database the
+        /// listener writes to. Only one program can listen on the port at a time
+        #[arg(long)]
+        udp: bool,
+        /// Address to listen on with --udp, in the same forms as the listener's --bind. Repeat
+        /// to listen on several at once. Defaults to the config file's listener.bind setting,
+        /// then "0.0.0.0"
+        #[arg(long, requires = "udp")]
+        bind: Vec<String>,
+        /// Port for addresses given without one. Defaults to the config file's listener.port
+        /// setting, then 50222
+        #[arg(long, requires = "udp")]
+        port: Option<u16>,
+        /// Serial number of a device or hub to show packets from with --udp. Repeat to show
+        /// several. Defaults to the config file's listener.serials setting, which shows
+        /// everything if empty
+        #[arg(long = "serial", requires = "udp")]
+        serials: Vec<String>,
+        /// How many seconds to wait between checks of the database
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Don't color the output. Colors are also left out when NO_COLOR is set or the output
+        /// isn't a terminal
+        #[arg(long)]
+        no_color: bool,
+    },
+}
+
+/// Parses a number of hours, which must be small enough to convert to seconds.
+fn parse_hours(value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|hours| hours.checked_mul(3600).is_some())
+        .ok_or_else(|| format!("expected a number of hours, got {}", value))
+}
+
+/// Parses a number of days, which must be small enough to convert to seconds.
+fn parse_days(value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|days| days.checked_mul(24 * 3600).is_some())
+        .ok_or_else(|| format!("expected a number of days, got {}", value))
+}
+
+/// Parses a Unix epoch, or a local date with an optional time of day.
+fn parse_time(value: &str) -> Result<u64, String> {
+    if let Ok(epoch) = value.parse::<u64>() {
+        return Ok(epoch);
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .map_err(|_| format!("expected an epoch or YYYY-MM-DD[ HH:MM], got {}", value))?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.timestamp() as u64)
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", value))
+}
+
+/// Formats an observation as a single line.
+fn format_row(weather: &Weather, units: &UnitPreferences) -> String {
+    format!(
+        "{}  {}  {}  {:.0}%  {}  wind {} gust {} {}  rain {}",
+        weather.get_time().unwrap().format("%b %-d %-I:%M %p"),
+        weather.serial_number,
+        units.temperature(weather.get_air_temp()),
+        weather.relative_humidity,
+        units.pressure(weather.get_station_pressure()),
+        units.speed(weather.get_wind_avg()),
+        units.speed(weather.get_wind_gust()),
+        weather.get_wind_direction(),
+        units.precipitation(weather.get_rain_over_prev_minute()),
+    )
+}
+
+/// Returns the value of a database `result`, or reports the error and exits if it failed.
+fn or_exit<T, E: fmt::Display>(result: Result<T, E>, action: &str) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("Unable to {}: {}", action, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn show_history(
+    conn: &Connection,
+    range: &ObservationRange,
+    units: Option<&UnitPreferences>,
+    json: bool,
+) {
+    let observations = or_exit(conn.get_observations_in_range(range), "get observations");
+
+    if json {
+        let reports: Vec<WeatherReport> = observations
+            .iter()
+            .map(|weather| WeatherReport::new(weather, None, None, units))
+            .collect();
 
-    let weather = conn
-        .get_latest_observation()
-        .expect("unable to get latest weather observation");
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+        return;
+    }
+
+    if observations.is_empty() {
+        println!("No observations in that span.");
+    }
+
+    for weather in observations {
+        println!(
+            "{}",
+            format_row(&weather, &units.copied().unwrap_or_default())
+        );
+    }
+}
+
+fn show_rollups(
+    conn: &Connection,
+    period: RollupPeriod,
+    days: u64,
+    serial: Option<&str>,
+    units: Option<&UnitPreferences>,
+    json: bool,
+) {
+    let end = now_epoch();
+    let start = period.period_start(end.saturating_sub(days * 24 * 3600));
+    let rollups = or_exit(
+        conn.get_rollups(period, start, end + 1, serial),
+        "get rollups",
+    );
+
+    if json {
+        let rollups: Vec<Rollup> = match units {
+            Some(units) => rollups
+                .iter()
+                .map(|rollup| rollup.in_units(units))
+                .collect(),
+            None => rollups,
+        };
+
+        println!("{}", serde_json::to_string_pretty(&rollups).unwrap());
+        return;
+    }
+
+    if rollups.is_empty() {
+        println!("No summaries in the last {} days.", days);
+    }
+
+    for rollup in rollups {
+        println!("{}", rollup.with_units(&units.copied().unwrap_or_default()));
+    }
+}
+
+fn show_latest(
+    conn: &Connection,
+    serial: Option<&str>,
+    trend_hours: u64,
+    units: Option<&UnitPreferences>,
+    config: &Config,
+) {
+    let weather = match or_exit(
+        conn.get_latest_observation(serial),
+        "get latest weather observation",
+    ) {
+        Some(weather) => weather,
+        None => {
+            eprintln!("No weather observations found.");
+            process::exit(1);
+        }
+    };
+    let pressure_trend = or_exit(
+        conn.get_pressure_trend(
+            weather.time_epoch,
+            trend_hours * 3600,
+            Some(&weather.serial_number),
+        ),
+        "get pressure trend",
+    );
 
     println!("FORMATTED WEATHER OBSERVATION:");
-    println!("{}", weather);
+    println!(
+        "{}",
+        weather
+            .display()
+            .with_station(config.station.as_ref())
+            .with_pressure_trend(pressure_trend.as_ref())
+            .with_units(&units.copied().unwrap_or_default())
+    );
+
+    let report = WeatherReport::new(
+        &weather,
+        config.station.as_ref(),
+        pressure_trend.as_ref(),
+        units,
+    );
 
     println!("JSON WEATHER OBSERVATION:");
-    println!("{}", serde_json::to_string_pretty(&weather).unwrap());
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
+
+fn show_rain(
+    conn: &Connection,
+    serial: Option<&str>,
+    day_start: u32,
+    units: Option<&UnitPreferences>,
+    json: bool,
+) {
+    let periods = RainPeriods::new(now_epoch(), day_start, &Local);
+    let totals = or_exit(conn.get_rain_totals(&periods, serial), "get rain totals");
+
+    if json {
+        let totals = units.map_or(totals, |units| totals.in_units(units));
+
+        println!("{}", serde_json::to_string_pretty(&totals).unwrap());
+    } else {
+        print!("{}", totals.with_units(&units.copied().unwrap_or_default()));
+    }
+}
+
+fn show_strikes(conn: &Connection, hours: u64, units: &UnitPreferences) {
+    let end = now_epoch();
+    let strikes = or_exit(
+        conn.get_lightning_strikes(end.saturating_sub(hours * 3600), end + 1),
+        "get lightning strikes",
+    );
+
+    if strikes.is_empty() {
+        println!("No lightning strikes in the last {} hours.", hours);
+    }
+
+    for strike in strikes {
+        println!("{}", strike.with_units(units));
+    }
+}
+
+fn show_rain_starts(conn: &Connection, hours: u64) {
+    let end = now_epoch();
+    let rain_starts = or_exit(
+        conn.get_rain_starts(end.saturating_sub(hours * 3600), end + 1),
+        "get rain starts",
+    );
+
+    if rain_starts.is_empty() {
+        println!("No rain started in the last {} hours.", hours);
+    }
+
+    for rain_start in rain_starts {
+        println!("{}", rain_start);
+    }
+}
+
+/// Formats the first and last values of a series along with its range and average, to the given
+/// number of decimal places.
+fn format_trend(values: &[f64], unit: &str, precision: usize) -> String {
+    let first = values[0];
+    let last = values[values.len() - 1];
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let avg = values.iter().sum::<f64>() / values.len() as f64;
+
+    format!(
+        "{:.*} {} (was {:.*} {}; min {:.*}, max {:.*}, avg {:.*})",
+        precision,
+        last,
+        unit,
+        precision,
+        first,
+        unit,
+        precision,
+        min,
+        precision,
+        max,
+        precision + 1,
+        avg
+    )
+}
+
+/// Groups status reports by serial number, keeping them in order.
+fn by_serial<T: StatusReport>(reports: &[T]) -> BTreeMap<&str, Vec<&T>> {
+    let mut groups: BTreeMap<&str, Vec<&T>> = BTreeMap::new();
+
+    for report in reports {
+        groups
+            .entry(report.serial_number())
+            .or_default()
+            .push(report);
+    }
+
+    groups
+}
+
+fn print_uptime_and_reboots(latest_uptime: u64, reboots: &[Reboot], serial_number: &str) {
+    println!(
+        "  Uptime: {}",
+        format_duration(Duration::seconds(latest_uptime as i64))
+    );
+
+    let reboots: Vec<&Reboot> = reboots
+        .iter()
+        .filter(|reboot| reboot.serial_number == serial_number)
+        .collect();
+
+    if reboots.is_empty() {
+        println!("  Reboots: none");
+        return;
+    }
+
+    println!("  Reboots: {}", reboots.len());
+    for reboot in reboots {
+        println!(
+            "    {} (after {})",
+            reboot.get_time().unwrap().format("%B %-d, %Y at %-I:%M %p"),
+            format_duration(Duration::seconds(reboot.previous_uptime as i64))
+        );
+    }
+}
+
+fn show_status(conn: &Connection, hours: u64) {
+    let end = now_epoch();
+    let start = end.saturating_sub(hours * 3600);
+
+    let device_statuses = or_exit(
+        conn.get_device_statuses(start, end + 1),
+        "get device statuses",
+    );
+    let hub_statuses = or_exit(conn.get_hub_statuses(start, end + 1), "get hub statuses");
+
+    if device_statuses.is_empty() && hub_statuses.is_empty() {
+        println!("No status reports in the last {} hours.", hours);
+        return;
+    }
+
+    let device_reboots = find_reboots(&device_statuses);
+    for (serial_number, reports) in by_serial(&device_statuses) {
+        let latest = reports[reports.len() - 1];
+        let voltages: Vec<f64> = reports.iter().map(|r| r.voltage as f64).collect();
+        let rssis: Vec<f64> = reports.iter().map(|r| r.rssi as f64).collect();
+        let hub_rssis: Vec<f64> = reports.iter().map(|r| r.hub_rssi as f64).collect();
+
+        println!(
+            "Device {} (via {}), {} reports",
+            serial_number,
+            latest.hub_sn,
+            reports.len()
+        );
+        println!("  Battery: {}", format_trend(&voltages, "V", 2));
+        println!("  Signal: {}", format_trend(&rssis, "dBm", 0));
+        println!("  Signal at hub: {}", format_trend(&hub_rssis, "dBm", 0));
+        println!("  Sensor status: {}", latest.get_sensor_flags());
+        print_uptime_and_reboots(latest.uptime, &device_reboots, serial_number);
+    }
+
+    let hub_reboots = find_reboots(&hub_statuses);
+    for (serial_number, reports) in by_serial(&hub_statuses) {
+        let latest = reports[reports.len() - 1];
+        let rssis: Vec<f64> = reports.iter().map(|r| r.rssi as f64).collect();
+
+        println!(
+            "Hub {} (firmware {}), {} reports",
+            serial_number,
+            latest.firmware_revision,
+            reports.len()
+        );
+        println!("  Signal: {}", format_trend(&rssis, "dBm", 0));
+        println!("  Reset flags: {}", latest.reset_flags);
+        print_uptime_and_reboots(latest.uptime, &hub_reboots, serial_number);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Unable to load config: {}", error);
+            process::exit(1);
+        }
+    };
+    let units = match cli.units {
+        Some(units) => Some(units),
+        None => match config.units.as_deref().map(str::parse).transpose() {
+            Ok(units) => units,
+            Err(error) => {
+                eprintln!("Invalid units in config: {}", error);
+                process::exit(1);
+            }
+        },
+    };
+
+    let default_command = Command::Latest {
+        serial: None,
+        trend_hours: 3,
+    };
+    let command = cli.command.unwrap_or(default_command);
+
+    // Watching the broadcasts doesn't need the database, so it isn't opened or created.
+    if let Command::Watch {
+        udp: true,
+        bind,
+        port,
+        serials,
+        no_color,
+        ..
+    } = command
+    {
+        let printer = Printer::new(units.unwrap_or_default(), no_color);
+        let listener_config = config.listener.unwrap_or_default();
+        let addresses = match listener_config.bind_addresses(&bind, port) {
+            Ok(addresses) => addresses,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        };
+        let serials = listener_config.accepted_serials(serials);
+        let merger = WeatherMerger::with_pairs(listener_config.pairs);
+
+        if let Err(error) = watch_udp(&addresses, &serials, merger, &printer) {
+            eprintln!("Unable to listen: {}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut conn = match db::connect(&DatabaseLocation::resolve(cli.db.as_deref(), &config)) {
+        Ok(conn) => conn,
+        Err(error) => {
+            eprintln!("Unable to open database: {}", error);
+            process::exit(1);
+        }
+    };
+
+    match command {
+        Command::Latest {
+            serial,
+            trend_hours,
+        } => show_latest(
+            &conn,
+            serial.as_deref(),
+            trend_hours,
+            units.as_ref(),
+            &config,
+        ),
+        Command::History {
+            from,
+            to,
+            serial,
+            asc,
+            limit,
+            offset,
+            json,
+        } => {
+            let range = ObservationRange {
+                from: Some(from.unwrap_or_else(|| now_epoch() - 24 * 3600)),
+                to,
+                serial_number: serial,
+                order: if asc {
+                    SortOrder::Ascending
+                } else {
+                    SortOrder::Descending
+                },
+                limit,
+                offset,
+            };
+
+            show_history(&conn, &range, units.as_ref(), json)
+        }
+        Command::Rollups {
+            hourly,
+            days,
+            serial,
+            rebuild,
+            json,
+        } => {
+            if rebuild {
+                let count = or_exit(conn.rebuild_rollups(), "rebuild rollups");
+                eprintln!("Rebuilt summaries from {} observations.", count);
+            }
+
+            let period = if hourly {
+                RollupPeriod::Hourly
+            } else {
+                RollupPeriod::Daily
+            };
+
+            show_rollups(&conn, period, days, serial.as_deref(), units.as_ref(), json)
+        }
+        Command::Prune { policy, dry_run } => {
+            let report = or_exit(conn.prune(&policy, now_epoch(), dry_run), "prune database");
+
+            print!("{}", report);
+        }
+        Command::Strikes { hours } => show_strikes(&conn, hours, &units.unwrap_or_default()),
+        Command::Rain {
+            serial,
+            day_start,
+            json,
+        } => {
+            let day_start = day_start
+                .or_else(|| config.station.map(|station| station.rain_day_start))
+                .unwrap_or(0);
+
+            show_rain(&conn, serial.as_deref(), day_start, units.as_ref(), json)
+        }
+        Command::RainStarts { hours } => show_rain_starts(&conn, hours),
+        Command::Status { hours } => show_status(&conn, hours),
+        Command::Watch {
+            interval, no_color, ..
+        } => {
+            let printer = Printer::new(units.unwrap_or_default(), no_color);
+
+            if let Err(error) = watch_db(&conn, StdDuration::from_secs(interval.max(1)), &printer) {
+                eprintln!("Unable to read database: {}", error);
+                process::exit(1);
+            }
+        }
+    }
 }
@@ -0,0 +1,282 @@
+use core::{
+    event::{IntoLightningStrike, IntoRainStart, LightningStrike, RainStart},
+    packet::Packet,
+    status::{DeviceStatus, HubStatus, IntoDeviceStatus, IntoHubStatus},
+    units::UnitPreferences,
+    util::format_duration,
+    weather::{IntoPartialWeather, IntoRapidWind, IntoWeather, RapidWind, Weather, WeatherMerger},
+};
+use db::{self, Connection, GetNewRows, RowIds};
+use std::{
+    io::{self, IsTerminal},
+    net::{SocketAddr, UdpSocket},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+/// Anything a station reports that's worth a line in the stream.
+pub enum Event {
+    Observation(Weather),
+    RapidWind(RapidWind),
+    LightningStrike(LightningStrike),
+    RainStart(RainStart),
+    DeviceStatus(DeviceStatus),
+    HubStatus(HubStatus),
+}
+
+impl Event {
+    /// Every event a packet carries. Observations split across `obs_air` and `obs_sky` packets
+    /// only produce an event once `merger` has both halves.
+    fn from_packet(packet: &Packet, merger: &mut WeatherMerger) -> Vec<Event> {
+        let weather = packet.into_weather().or_else(|| {
+            let part = packet.into_partial_weather()?;
+            merger.merge(part)
+        });
+
+        let events = [
+            weather.map(Event::Observation),
+            packet.into_rapid_wind().map(Event::RapidWind),
+            packet.into_lightning_strike().map(Event::LightningStrike),
+            packet.into_rain_start().map(Event::RainStart),
+            packet.into_device_status().map(Event::DeviceStatus),
+            packet.into_hub_status().map(Event::HubStatus),
+        ];
+
+        events.into_iter().flatten().collect()
+    }
+
+    fn time_epoch(&self) -> u64 {
+        match self {
+            Event::Observation(weather) => weather.time_epoch,
+            Event::RapidWind(sample) => sample.time_epoch,
+            Event::LightningStrike(strike) => strike.time_epoch,
+            Event::RainStart(rain_start) => rain_start.time_epoch,
+            Event::DeviceStatus(status) => status.time_epoch,
+            Event::HubStatus(status) => status.time_epoch,
+        }
+    }
+
+    fn serial_number(&self) -> &str {
+        match self {
+            Event::Observation(weather) => &weather.serial_number,
+            Event::RapidWind(sample) => &sample.serial_number,
+            Event::LightningStrike(strike) => &strike.serial_number,
+            Event::RainStart(rain_start) => &rain_start.serial_number,
+            Event::DeviceStatus(status) => &status.serial_number,
+            Event::HubStatus(status) => &status.serial_number,
+        }
+    }
+
+    /// A short label for the kind of event, and the ANSI color it's shown in.
+    fn label(&self) -> (&'static str, &'static str) {
+        match self {
+            Event::Observation(_) => ("OBS", "32"),
+            Event::RapidWind(_) => ("WIND", "36"),
+            Event::LightningStrike(_) => ("STRIKE", "1;33"),
+            Event::RainStart(_) => ("RAIN", "1;34"),
+            Event::DeviceStatus(_) => ("STATUS", "35"),
+            Event::HubStatus(_) => ("HUB", "35"),
+        }
+    }
+
+    fn details(&self, units: &UnitPreferences) -> String {
+        match self {
+            Event::Observation(weather) => format!(
+                "{}  {:.0}%  {}  wind {} gust {} {}  rain {}",
+                units.temperature(weather.get_air_temp()),
+                weather.relative_humidity,
+                units.pressure(weather.get_station_pressure()),
+                units.speed(weather.get_wind_avg()),
+                units.speed(weather.get_wind_gust()),
+                weather.get_wind_direction(),
+                units.precipitation(weather.get_rain_over_prev_minute()),
+            ),
+            Event::RapidWind(sample) => format!(
+                "{} {}",
+                units.speed(sample.get_wind_speed()),
+                sample.get_wind_direction()
+            ),
+            Event::LightningStrike(strike) => format!(
+                "lightning {} away (energy {})",
+                units.distance(strike.get_distance()),
+                strike.energy
+            ),
+            Event::RainStart(_) => "rain started".to_string(),
+            Event::DeviceStatus(status) => format!(
+                "battery {:.2} V  signal {} dBm  {}",
+                status.voltage,
+                status.rssi,
+                status.get_sensor_flags()
+            ),
+            Event::HubStatus(status) => format!(
+                "firmware {}  signal {} dBm  up {}",
+                status.firmware_revision,
+                status.rssi,
+                format_duration(chrono::Duration::seconds(status.uptime as i64))
+            ),
+        }
+    }
+}
+
+/// How events are printed.
+pub struct Printer {
+    units: UnitPreferences,
+    color: bool,
+}
+
+impl Printer {
+    /// Colors are used unless they're turned off, `NO_COLOR` is set, or output isn't a terminal.
+    pub fn new(units: UnitPreferences, no_color: bool) -> Self {
+        let color =
+            !no_color && std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+
+        Self { units, color }
+    }
+
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn print(&self, event: &Event) {
+        let time = chrono::DateTime::from_timestamp(event.time_epoch() as i64, 0)
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%-I:%M:%S %p")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let (label, code) = event.label();
+
+        println!(
+            "{}  {}  {}  {}",
+            self.paint(&format!("{:>11}", time), "2"),
+            self.paint(&format!("{:<6}", label), code),
+            event.serial_number(),
+            event.details(&self.units)
+        );
+    }
+
+    pub fn print_error(&self, message: &str) {
+        eprintln!("{}", self.paint(message, "31"));
+    }
+}
+
+/// Receives datagrams on `socket` until the program exits, sending each, or the error receiving
+/// it, to `sender`.
+fn receive(socket: UdpSocket, sender: Sender<io::Result<Vec<u8>>>) {
+    let mut buf = [0u8; 64000];
+
+    loop {
+        let received = socket
+            .recv(&mut buf)
+            .map(|num_bytes| buf[0..num_bytes].to_vec());
+
+        if sender.send(received).is_err() {
+            return;
+        }
+    }
+}
+
+/**
+Prints every packet broadcast to `addresses` as it arrives, until interrupted, the same way the
+listener receives them. Packets from devices and hubs that aren't in `serials` are skipped, unless
+it's empty, as are packets of unknown types. AIR and SKY observations are combined by `merger`.
+*/
+pub fn watch_udp(
+    addresses: &[SocketAddr],
+    serials: &[String],
+    mut merger: WeatherMerger,
+    printer: &Printer,
+) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+
+    for address in addresses {
+        let socket = UdpSocket::bind(address)
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", address, error)))?;
+        let sender = sender.clone();
+
+        thread::spawn(move || receive(socket, sender));
+    }
+    drop(sender);
+
+    for received in receiver {
+        let payload = match received {
+            Ok(payload) => payload,
+            Err(error) => {
+                printer.print_error(&format!("Network error: {}", error));
+                continue;
+            }
+        };
+
+        let packet = match Packet::parse(&payload) {
+            Ok(packet) => packet,
+            Err(core::error::Error::UnknownPacketType(_)) => continue,
+            Err(error) => {
+                printer.print_error(&format!("Unable to parse packet: {}", error));
+                continue;
+            }
+        };
+
+        let accepted = serials.is_empty()
+            || [packet.serial_number(), packet.hub_sn()]
+                .into_iter()
+                .flatten()
+                .any(|serial| serials.iter().any(|allowed| allowed == serial));
+
+        if accepted {
+            for event in Event::from_packet(&packet, &mut merger) {
+                printer.print(&event);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Everything stored after `ids`, oldest first. `ids` moves past what's returned.
+fn stored_events(conn: &Connection, ids: &mut RowIds) -> Result<Vec<Event>, db::Error> {
+    let rows = conn.get_new_rows(ids)?;
+
+    let mut events: Vec<Event> = rows
+        .observations
+        .into_iter()
+        .map(Event::Observation)
+        .collect();
+    events.extend(rows.rapid_wind.into_iter().map(Event::RapidWind));
+    events.extend(
+        rows.lightning_strikes
+            .into_iter()
+            .map(Event::LightningStrike),
+    );
+    events.extend(rows.rain_starts.into_iter().map(Event::RainStart));
+    events.extend(rows.device_statuses.into_iter().map(Event::DeviceStatus));
+    events.extend(rows.hub_statuses.into_iter().map(Event::HubStatus));
+
+    events.sort_by_key(Event::time_epoch);
+
+    Ok(events)
+}
+
+/**
+Prints everything the listener stores from now on, checking the database every `interval`, until
+interrupted.
+
+Each check picks up the rows stored since the last one, by their ids, so rows that arrive late or
+out of order, since each device reports on its own clock, are still printed, once each.
+*/
+pub fn watch_db(conn: &Connection, interval: Duration, printer: &Printer) -> Result<(), db::Error> {
+    let mut ids = conn.get_latest_row_ids()?;
+
+    loop {
+        for event in stored_events(conn, &mut ids)? {
+            printer.print(&event);
+        }
+
+        thread::sleep(interval);
+    }
+}
@@ -0,0 +1,237 @@
+use serde::Serialize;
+
+use crate::units::{Speed, SpeedUnit, TempUnit, Temperature, UnitPreferences};
+
+/// Coefficients for the Magnus approximation of saturation vapor pressure over water, from
+/// Alduchov and Eskridge (1996).
+const MAGNUS_A: f32 = 17.625;
+const MAGNUS_B: f32 = 243.04;
+
+/// Saturation vapor pressure in hPa at the given temperature in °C.
+fn saturation_vapor_pressure(air_temp: f32) -> f32 {
+    6.1094 * (MAGNUS_A * air_temp / (MAGNUS_B + air_temp)).exp()
+}
+
+/// Actual vapor pressure in hPa at the given temperature in °C and relative humidity in %.
+fn vapor_pressure(air_temp: f32, relative_humidity: f32) -> f32 {
+    relative_humidity / 100.0 * saturation_vapor_pressure(air_temp)
+}
+
+/// The temperature to which air must be cooled to become saturated. Humidity is clamped to 1%,
+/// since dew point is undefined for perfectly dry air.
+pub fn dew_point(air_temp: f32, relative_humidity: f32) -> Temperature {
+    let gamma = (relative_humidity.clamp(1.0, 100.0) / 100.0).ln()
+        + MAGNUS_A * air_temp / (MAGNUS_B + air_temp);
+
+    Temperature::new(MAGNUS_B * gamma / (MAGNUS_A - gamma), TempUnit::C)
+}
+
+/**
+The NWS heat index, following the algorithm used by the Weather Prediction Center: Steadman's
+simple formula is used first, and if the result averaged with the temperature is 80°F or more, the
+Rothfusz regression is used instead, with its adjustments for very dry and very humid air.
+
+See <https://www.wpc.ncep.noaa.gov/html/heatindex_equation.shtml>.
+*/
+pub fn heat_index(air_temp: f32, relative_humidity: f32) -> Temperature {
+    let t = Temperature::new(air_temp, TempUnit::C).into_f().value();
+    let rh = relative_humidity;
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+
+    if (simple + t) / 2.0 < 80.0 {
+        return Temperature::new(simple, TempUnit::F).into_c();
+    }
+
+    let mut index = -42.379 + 2.049_015_2 * t + 10.143_331 * rh
+        - 0.224_755_42 * t * rh
+        - 0.006_837_83 * t * t
+        - 0.054_817_17 * rh * rh
+        + 0.001_228_74 * t * t * rh
+        + 0.000_852_82 * t * rh * rh
+        - 0.000_001_99 * t * t * rh * rh;
+
+    if rh < 13.0 && (80.0..=112.0).contains(&t) {
+        index -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+    } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+        index += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+    }
+
+    Temperature::new(index, TempUnit::F).into_c()
+}
+
+/// The NWS wind chill. It's only defined at or below 50°F with wind of at least 3 mph; outside
+/// that range the air temperature is returned unchanged.
+pub fn wind_chill(air_temp: f32, wind_speed: f32) -> Temperature {
+    let t = Temperature::new(air_temp, TempUnit::C).into_f().value();
+    let v = Speed::new(wind_speed, SpeedUnit::MetersPerSecond)
+        .into_miles_per_hour()
+        .value();
+
+    if t > 50.0 || v < 3.0 {
+        return Temperature::new(air_temp, TempUnit::C);
+    }
+
+    let v16 = v.powf(0.16);
+    let chill = 35.74 + 0.6215 * t - 35.75 * v16 + 0.4275 * t * v16;
+
+    Temperature::new(chill, TempUnit::F).into_c()
+}
+
+/// What the temperature feels like, as the NWS reports it: the wind chill when it's cold and
+/// windy, the heat index when it's hot, and the air temperature otherwise.
+pub fn feels_like(air_temp: f32, relative_humidity: f32, wind_speed: f32) -> Temperature {
+    let t = Temperature::new(air_temp, TempUnit::C).into_f().value();
+
+    if t <= 50.0 {
+        wind_chill(air_temp, wind_speed)
+    } else if t >= 80.0 {
+        heat_index(air_temp, relative_humidity)
+    } else {
+        Temperature::new(air_temp, TempUnit::C)
+    }
+}
+
+/// Steadman's apparent temperature for shade, as used by the Australian Bureau of Meteorology,
+/// which accounts for humidity and wind across the whole temperature range.
+pub fn apparent_temperature(air_temp: f32, relative_humidity: f32, wind_speed: f32) -> Temperature {
+    let e = vapor_pressure(air_temp, relative_humidity);
+
+    Temperature::new(air_temp + 0.33 * e - 0.70 * wind_speed - 4.00, TempUnit::C)
+}
+
+/// The wet-bulb temperature, using Stull's (2011) empirical formula, which is accurate to within
+/// about 1°C for humidity between 5% and 99% and temperatures between -20°C and 50°C.
+pub fn wet_bulb(air_temp: f32, relative_humidity: f32) -> Temperature {
+    let t = air_temp;
+    let rh = relative_humidity;
+
+    let wet_bulb = t * (0.151_977 * (rh + 8.313_659).sqrt()).atan() + (t + rh).atan()
+        - (rh - 1.676_331).atan()
+        + 0.003_918_38 * rh.powf(1.5) * (0.023_101 * rh).atan()
+        - 4.686_035;
+
+    Temperature::new(wet_bulb, TempUnit::C)
+}
+
+/// The mass of water vapor in the air, in g/m^3.
+pub fn absolute_humidity(air_temp: f32, relative_humidity: f32) -> f32 {
+    // The ideal gas law with the specific gas constant for water vapor, 461.5 J/(kg·K), with the
+    // vapor pressure converted from hPa to Pa and the result from kg to g.
+    vapor_pressure(air_temp, relative_humidity) * 100_000.0 / (461.5 * (air_temp + 273.15))
+}
+
+/// Every derived value for an observation, in °C and g/m^3, for JSON output.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DerivedMetrics {
+    pub dew_point: f32,
+    pub heat_index: f32,
+    pub wind_chill: f32,
+    pub feels_like: f32,
+    pub apparent_temperature: f32,
+    pub wet_bulb: f32,
+    pub absolute_humidity: f32,
+}
+
+impl DerivedMetrics {
+    pub fn new(air_temp: f32, relative_humidity: f32, wind_speed: f32) -> Self {
+        Self {
+            dew_point: dew_point(air_temp, relative_humidity).value(),
+            heat_index: heat_index(air_temp, relative_humidity).value(),
+            wind_chill: wind_chill(air_temp, wind_speed).value(),
+            feels_like: feels_like(air_temp, relative_humidity, wind_speed).value(),
+            apparent_temperature: apparent_temperature(air_temp, relative_humidity, wind_speed)
+                .value(),
+            wet_bulb: wet_bulb(air_temp, relative_humidity).value(),
+            absolute_humidity: absolute_humidity(air_temp, relative_humidity),
+        }
+    }
+
+    /// The same metrics with temperatures in the given units.
+    pub fn in_units(&self, units: &UnitPreferences) -> Self {
+        let temperature = |value| {
+            units
+                .temperature(Temperature::new(value, TempUnit::C))
+                .value()
+        };
+
+        Self {
+            dew_point: temperature(self.dew_point),
+            heat_index: temperature(self.heat_index),
+            wind_chill: temperature(self.wind_chill),
+            feels_like: temperature(self.feels_like),
+            apparent_temperature: temperature(self.apparent_temperature),
+            wet_bulb: temperature(self.wet_bulb),
+            absolute_humidity: self.absolute_humidity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {} ± {}, got {}",
+            expected,
+            tolerance,
+            actual
+        );
+    }
+
+    #[test]
+    fn computes_dew_point() {
+        assert_close(dew_point(20.0, 50.0).value(), 9.3, 0.1);
+        assert_close(dew_point(-5.0, 80.0).value(), -7.9, 0.1);
+        assert_close(dew_point(25.0, 100.0).value(), 25.0, 0.01);
+        assert!(dew_point(25.0, 0.0).value().is_finite());
+    }
+
+    #[test]
+    fn computes_heat_index() {
+        // From the NWS heat index chart: 90°F at 70% feels like 106°F.
+        let index = heat_index(Temperature::new(90.0, TempUnit::F).into_c().value(), 70.0);
+        assert_close(index.into_f().value(), 106.0, 1.0);
+
+        // Below 80°F the simple formula is used, which stays close to the air temperature.
+        assert_close(heat_index(20.0, 50.0).value(), 20.0, 1.5);
+    }
+
+    #[test]
+    fn computes_wind_chill() {
+        // From the NWS wind chill chart: 15°F with 20 mph of wind feels like -2°F.
+        let chill = wind_chill(
+            Temperature::new(15.0, TempUnit::F).into_c().value(),
+            Speed::new(20.0, SpeedUnit::MilesPerHour)
+                .into_meters_per_second()
+                .value(),
+        );
+        assert_close(chill.into_f().value(), -2.0, 0.5);
+
+        assert_eq!(wind_chill(15.0, 10.0).value(), 15.0);
+        assert_eq!(wind_chill(-5.0, 0.5).value(), -5.0);
+    }
+
+    #[test]
+    fn picks_feels_like_by_temperature() {
+        assert_eq!(
+            feels_like(-5.0, 50.0, 5.0).value(),
+            wind_chill(-5.0, 5.0).value()
+        );
+        assert_eq!(
+            feels_like(32.0, 60.0, 5.0).value(),
+            heat_index(32.0, 60.0).value()
+        );
+        assert_eq!(feels_like(18.0, 60.0, 5.0).value(), 18.0);
+    }
+
+    #[test]
+    fn computes_wet_bulb_and_absolute_humidity() {
+        // Stull (2011) gives 13.7°C for 20°C at 50%.
+        assert_close(wet_bulb(20.0, 50.0).value(), 13.7, 0.1);
+        assert_close(absolute_humidity(20.0, 100.0), 17.3, 0.1);
+        assert_close(absolute_humidity(20.0, 0.0), 0.0, 0.001);
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt::Display;
+
+/// Why a datagram couldn't be read as a packet.
+#[derive(Debug)]
+pub enum Error {
+    /// The datagram isn't JSON, or doesn't match the schema for its type of packet.
+    InvalidPacket(serde_json::Error),
+    /// The datagram is a type of packet that isn't supported, e.g. one added by newer firmware.
+    UnknownPacketType(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidPacket(error) => write!(f, "invalid packet: {}", error),
+            Error::UnknownPacketType(kind) => write!(f, "unknown packet type: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::InvalidPacket(error)
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Local, LocalResult, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::units::{Distance, DistanceUnit, FormatWithUnits, UnitPreferences};
+
+/// A single lightning strike detected by a device, from an `evt_strike` packet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LightningStrike {
+    pub time_epoch: u64,
+    /// Estimated distance to the strike, in km.
+    pub distance: u32,
+    /// Unitless energy of the strike, as reported by the device.
+    pub energy: u64,
+    pub serial_number: String,
+}
+
+impl LightningStrike {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.time_epoch as i64, 0)
+    }
+}
+
+impl LightningStrike {
+    pub fn get_distance(&self) -> Distance {
+        Distance::new(self.distance as f32, DistanceUnit::Kilometers)
+    }
+}
+
+impl FormatWithUnits for LightningStrike {
+    fn fmt_with_units(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        units: &UnitPreferences,
+    ) -> std::fmt::Result {
+        let display_time = self
+            .get_time()
+            .unwrap()
+            .format("%B %-d, %Y at %-I:%M:%S %p");
+
+        write!(
+            f,
+            "{}: lightning strike {} away (energy {}) from {}",
+            display_time,
+            units.distance(self.get_distance()),
+            self.energy,
+            self.serial_number
+        )
+    }
+}
+
+impl Display for LightningStrike {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_units(f, &UnitPreferences::default())
+    }
+}
+
+pub trait IntoLightningStrike {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_lightning_strike(&self) -> Option<LightningStrike>;
+}
+
+/// The onset of rain detected by a device, from an `evt_precip` packet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RainStart {
+    pub time_epoch: u64,
+    pub serial_number: String,
+}
+
+impl RainStart {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.time_epoch as i64, 0)
+    }
+}
+
+impl Display for RainStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_time = self
+            .get_time()
+            .unwrap()
+            .format("%B %-d, %Y at %-I:%M:%S %p");
+
+        write!(
+            f,
+            "{}: rain started at {}",
+            display_time, self.serial_number
+        )
+    }
+}
+
+pub trait IntoRainStart {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_rain_start(&self) -> Option<RainStart>;
+}
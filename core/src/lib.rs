@@ -1,5 +1,16 @@
+pub mod derived;
+pub mod error;
+pub mod event;
+pub mod outbox;
 pub mod packet;
 pub mod queries;
+pub mod rain;
+pub mod retention;
+pub mod rollup;
+pub mod station;
+pub mod status;
+pub mod trend;
 pub mod units;
 pub mod util;
 pub mod weather;
+pub mod wind;
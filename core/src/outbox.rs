@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// A record waiting in the `outbox` table to be uploaded: the JSON `body` to `POST` to `path` on
+/// the upload server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub path: String,
+    pub body: String,
+    pub created_epoch: u64,
+    /// How many uploads of this record have failed so far.
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
@@ -1,6 +1,12 @@
-use crate::weather::{IntoWeather, Weather};
+use crate::error::Error;
+use crate::event::{IntoLightningStrike, IntoRainStart, LightningStrike, RainStart};
+use crate::status::{self, IntoDeviceStatus, IntoHubStatus};
+use crate::weather::{
+    AirWeather, IntoPartialWeather, IntoRapidWind, IntoWeather, PartialWeather, RapidWind,
+    SkyWeather, Weather,
+};
 use serde::de::{self, Deserializer, Unexpected};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
 fn bool_from_int<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
@@ -16,6 +22,14 @@ where
     }
 }
 
+/// Serializes a flag as zero or one, the way devices send it, so packets round-trip.
+fn bool_to_int<S>(value: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u8(*value as u8)
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum Packet {
@@ -47,6 +61,52 @@ pub enum Packet {
         obs: [[f64; 18]; 1],
     },
 
+    #[serde(rename = "obs_air")]
+    AirObservation {
+        serial_number: String,
+        hub_sn: String,
+        firmware_revision: u64,
+        /**
+        0: Time Epoch, Seconds
+        1: Station Pressure, MB
+        2: Air Temperature, C
+        3: Relative Humidity, %
+        4: Lightning Strike Count, count
+        5: Lightning Strike Avg Distance, km
+        6: Battery, Volts
+        7: Report Interval, Minutes
+
+        Any of these may be null, so they're kept as options.
+        */
+        obs: [[Option<f64>; 8]; 1],
+    },
+
+    #[serde(rename = "obs_sky")]
+    SkyObservation {
+        serial_number: String,
+        hub_sn: String,
+        firmware_revision: u64,
+        /**
+        0: Time Epoch, Seconds
+        1: Illuminance, Lux
+        2: UV, Index
+        3: Rain amount over previous minute, mm
+        4: Wind Lull (minimum 3 second sample), m/s
+        5: Wind Avg (average over report interval), m/s
+        6: Wind Gust (maximum 3 second sample), m/s
+        7: Wind Direction, Degrees
+        8: Battery, Volts
+        9: Report Interval, Minutes
+        10: Solar Radiation, W/m^2
+        11: Local Day Rain Accumulation, mm (always null)
+        12: Precipitation Type, 0 = none; 1 = rain; 2 = hail; 3 = rain + hail
+        13: Wind Sample Interval, seconds
+
+        Any of these may be null, so they're kept as options.
+        */
+        obs: [[Option<f64>; 14]; 1],
+    },
+
     #[serde(rename = "rapid_wind")]
     RapidWind {
         serial_number: String,
@@ -90,19 +150,19 @@ pub enum Packet {
         rssi: i64,
         hub_rssi: i64,
         /**
-         * 0b000000000	Sensors OK
-         * 0b000000001	lightning failed
-         * 0b000000010	lightning noise
-         * 0b000000100	lightning disturber
-         * 0b000001000	pressure failed
-         * 0b000010000	temperature failed
-         * 0b000100000	rh failed
-         * 0b001000000	wind failed
-         * 0b010000000	precip failed
-         * 0b100000000	light/uv failed
+         * 0b000000000 Sensors OK
+         * 0b000000001 lightning failed
+         * 0b000000010 lightning noise
+         * 0b000000100 lightning disturber
+         * 0b000001000 pressure failed
+         * 0b000010000 temperature failed
+         * 0b000100000 rh failed
+         * 0b001000000 wind failed
+         * 0b010000000 precip failed
+         * 0b100000000 light/uv failed
          */
         sensor_status: u64,
-        #[serde(deserialize_with = "bool_from_int")]
+        #[serde(deserialize_with = "bool_from_int", serialize_with = "bool_to_int")]
         debug: bool,
     },
 
@@ -125,10 +185,69 @@ pub enum Packet {
     Other,
 }
 
+/// Just the type of a packet, for reporting packets of unknown types.
+#[derive(Deserialize)]
+struct PacketType {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+impl Packet {
+    /// Reads a datagram as a packet. Unlike deserializing it directly, a packet of an unknown
+    /// type is an error rather than `Packet::Other`.
+    pub fn parse(payload: &[u8]) -> Result<Packet, Error> {
+        match serde_json::from_slice(payload)? {
+            Packet::Other => {
+                let packet_type: PacketType = serde_json::from_slice(payload)?;
+
+                Err(Error::UnknownPacketType(packet_type.kind))
+            }
+            packet => Ok(packet),
+        }
+    }
+
+    /// The serial number of the hub that relayed this packet, if the packet has one. Hubs report
+    /// their own serial number as `serial_number` in `hub_status` packets.
+    pub fn hub_sn(&self) -> Option<&str> {
+        match self {
+            Packet::Observation { hub_sn, .. }
+            | Packet::AirObservation { hub_sn, .. }
+            | Packet::SkyObservation { hub_sn, .. }
+            | Packet::RapidWind { hub_sn, .. }
+            | Packet::EventRainStart { hub_sn, .. }
+            | Packet::EventLightningStrike { hub_sn, .. }
+            | Packet::DeviceStatus { hub_sn, .. } => Some(hub_sn),
+            Packet::HubStatus { serial_number, .. } => Some(serial_number),
+            Packet::Other => None,
+        }
+    }
+
+    /// The serial number of the device that sent this packet, or of the hub for `hub_status`
+    /// packets.
+    pub fn serial_number(&self) -> Option<&str> {
+        match self {
+            Packet::Observation { serial_number, .. }
+            | Packet::AirObservation { serial_number, .. }
+            | Packet::SkyObservation { serial_number, .. }
+            | Packet::RapidWind { serial_number, .. }
+            | Packet::EventRainStart { serial_number, .. }
+            | Packet::EventLightningStrike { serial_number, .. }
+            | Packet::DeviceStatus { serial_number, .. }
+            | Packet::HubStatus { serial_number, .. } => Some(serial_number),
+            Packet::Other => None,
+        }
+    }
+}
+
 impl IntoWeather for Packet {
     fn into_weather(&self) -> Option<Weather> {
         match self {
-            Packet::Observation { obs, .. } => {
+            Packet::Observation {
+                serial_number,
+                hub_sn,
+                obs,
+                ..
+            } => {
                 let obs = obs[0];
 
                 Some(Weather {
@@ -146,13 +265,186 @@ impl IntoWeather for Packet {
                     solar_radiation: obs[11] as u32,
                     rain_over_prev_minute: obs[12] as f32,
                     precip_type: obs[13].into(),
-                    lightning_avg_distance: obs[14] as u32,
+                    lightning_avg_distance: Some(obs[14] as u32),
                     lightning_strike_count: obs[15] as u32,
                     battery_voltage: obs[16] as f32,
                     report_interval: obs[17] as u16,
+                    serial_number: serial_number.clone(),
+                    hub_sn: hub_sn.clone(),
                 })
             }
             _ => None,
         }
     }
 }
+
+/**
+Halves of legacy observations can have null fields. The average distance of lightning is null
+when there weren't any strikes, so it's kept as null. Any other field being null means the half
+can't be used, so there isn't one.
+*/
+impl IntoPartialWeather for Packet {
+    fn into_partial_weather(&self) -> Option<PartialWeather> {
+        match self {
+            Packet::AirObservation {
+                serial_number,
+                hub_sn,
+                obs,
+                ..
+            } => {
+                let [time_epoch, station_pressure, air_temp, relative_humidity, lightning_strike_count, lightning_avg_distance, battery_voltage, report_interval] =
+                    obs[0];
+
+                Some(PartialWeather::Air(AirWeather {
+                    serial_number: serial_number.clone(),
+                    hub_sn: hub_sn.clone(),
+                    time_epoch: time_epoch? as u64,
+                    station_pressure: station_pressure? as f32,
+                    air_temp: air_temp? as f32,
+                    relative_humidity: relative_humidity? as f32,
+                    lightning_strike_count: lightning_strike_count? as u32,
+                    lightning_avg_distance: lightning_avg_distance.map(|distance| distance as u32),
+                    battery_voltage: battery_voltage? as f32,
+                    report_interval: report_interval? as u16,
+                }))
+            }
+            Packet::SkyObservation {
+                serial_number,
+                hub_sn,
+                obs,
+                ..
+            } => {
+                let obs = obs[0];
+
+                Some(PartialWeather::Sky(SkyWeather {
+                    serial_number: serial_number.clone(),
+                    hub_sn: hub_sn.clone(),
+                    time_epoch: obs[0]? as u64,
+                    illuminance: obs[1]? as u32,
+                    uv_index: obs[2]? as f32,
+                    rain_over_prev_minute: obs[3]? as f32,
+                    wind_lull: obs[4]? as f32,
+                    wind_avg: obs[5]? as f32,
+                    wind_gust: obs[6]? as f32,
+                    wind_direction: obs[7]? as u16,
+                    battery_voltage: obs[8]? as f32,
+                    report_interval: obs[9]? as u16,
+                    solar_radiation: obs[10]? as u32,
+                    // obs[11], the day's rain, is always null.
+                    precip_type: obs[12]?.into(),
+                    wind_sample_interval: obs[13]? as u16,
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl IntoRapidWind for Packet {
+    fn into_rapid_wind(&self) -> Option<RapidWind> {
+        match self {
+            Packet::RapidWind {
+                serial_number, ob, ..
+            } => Some(RapidWind {
+                time_epoch: ob.0,
+                wind_speed: ob.1 as f32,
+                wind_direction: ob.2 as u16,
+                serial_number: serial_number.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLightningStrike for Packet {
+    fn into_lightning_strike(&self) -> Option<LightningStrike> {
+        match self {
+            Packet::EventLightningStrike {
+                serial_number, evt, ..
+            } => Some(LightningStrike {
+                time_epoch: evt[0],
+                distance: evt[1] as u32,
+                energy: evt[2],
+                serial_number: serial_number.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl IntoRainStart for Packet {
+    fn into_rain_start(&self) -> Option<RainStart> {
+        match self {
+            Packet::EventRainStart {
+                serial_number, evt, ..
+            } => Some(RainStart {
+                time_epoch: evt[0],
+                serial_number: serial_number.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl IntoDeviceStatus for Packet {
+    fn into_device_status(&self) -> Option<status::DeviceStatus> {
+        match self {
+            Packet::DeviceStatus {
+                serial_number,
+                hub_sn,
+                timestamp,
+                uptime,
+                voltage,
+                firmware_revision,
+                rssi,
+                hub_rssi,
+                sensor_status,
+                debug,
+            } => Some(status::DeviceStatus {
+                time_epoch: *timestamp,
+                serial_number: serial_number.clone(),
+                hub_sn: hub_sn.clone(),
+                uptime: *uptime,
+                voltage: *voltage as f32,
+                firmware_revision: *firmware_revision,
+                rssi: *rssi,
+                hub_rssi: *hub_rssi,
+                sensor_status: *sensor_status,
+                debug: *debug,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl IntoHubStatus for Packet {
+    fn into_hub_status(&self) -> Option<status::HubStatus> {
+        match self {
+            Packet::HubStatus {
+                serial_number,
+                firmware_revision,
+                uptime,
+                rssi,
+                timestamp,
+                reset_flags,
+                seq,
+                radio_stats,
+                ..
+            } => Some(status::HubStatus {
+                time_epoch: *timestamp,
+                serial_number: serial_number.clone(),
+                firmware_revision: firmware_revision.clone(),
+                uptime: *uptime,
+                rssi: *rssi,
+                reset_flags: reset_flags.clone(),
+                seq: *seq,
+                radio_version: radio_stats[0],
+                reboot_count: radio_stats[1],
+                bus_error_count: radio_stats[2],
+                radio_status: radio_stats[3],
+                radio_network_id: radio_stats[4],
+            }),
+            _ => None,
+        }
+    }
+}
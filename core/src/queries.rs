@@ -16,7 +16,9 @@ pub const QUERY_INSERT_OBSERVATION: &str = "INSERT INTO observation (
     lightning_avg_distance,
     lightning_strike_count,
     battery_voltage,
-    report_interval
+    report_interval,
+    serial_number,
+    hub_sn
 )
 VALUES (
     ?1,
@@ -36,8 +38,12 @@ VALUES (
     ?15,
     ?16,
     ?17,
-    ?18
-)";
+    ?18,
+    ?19,
+    ?20
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
 
 pub const QUERY_CREATE_TABLE_OBSERVATION: &str = "CREATE TABLE IF NOT EXISTS observation (
     id INTEGER PRIMARY KEY,
@@ -60,3 +66,915 @@ pub const QUERY_CREATE_TABLE_OBSERVATION: &str = "CREATE TABLE IF NOT EXISTS obs
     battery_voltage REAL,
     report_interval INTEGER
 )";
+
+pub const QUERY_ALTER_OBSERVATION_ADD_SERIAL_NUMBER: &str =
+    "ALTER TABLE observation ADD COLUMN serial_number TEXT";
+
+pub const QUERY_ALTER_OBSERVATION_ADD_HUB_SN: &str =
+    "ALTER TABLE observation ADD COLUMN hub_sn TEXT";
+
+// Observations merged from an AIR and a SKY are stored under both serial numbers, as
+// "AR-…/SK-…", so filters on the serial number of observations and rollups match either device.
+pub const QUERY_SELECT_OBSERVATIONS: &str = "SELECT * FROM observation
+WHERE ?2 IS NULL OR instr('/' || serial_number || '/', '/' || ?2 || '/') > 0
+ORDER BY id DESC LIMIT ?1";
+
+pub const QUERY_SELECT_OBSERVATIONS_RANGE_ASC: &str = "SELECT * FROM observation
+WHERE time_epoch >= ?1 AND time_epoch < ?2 AND (?3 IS NULL OR instr('/' || serial_number || '/', '/' || ?3 || '/') > 0)
+ORDER BY time_epoch ASC LIMIT ?4 OFFSET ?5";
+
+pub const QUERY_SELECT_OBSERVATIONS_RANGE_DESC: &str = "SELECT * FROM observation
+WHERE time_epoch >= ?1 AND time_epoch < ?2 AND (?3 IS NULL OR instr('/' || serial_number || '/', '/' || ?3 || '/') > 0)
+ORDER BY time_epoch DESC LIMIT ?4 OFFSET ?5";
+
+pub const QUERY_SELECT_OBSERVATIONS_AFTER_ID: &str = "SELECT * FROM observation
+WHERE id > ?1
+ORDER BY id ASC";
+
+pub const QUERY_SELECT_PRESSURE_SAMPLES: &str =
+    "SELECT time_epoch, station_pressure FROM observation
+WHERE time_epoch >= ?1 AND time_epoch < ?2 AND (?3 IS NULL OR instr('/' || serial_number || '/', '/' || ?3 || '/') > 0)
+ORDER BY time_epoch ASC";
+
+pub const QUERY_CREATE_INDEX_OBSERVATION_TIME: &str =
+    "CREATE INDEX IF NOT EXISTS observation_time_epoch ON observation (time_epoch)";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+/// Selects a page of observations by time. `from` is inclusive and `to` is exclusive; either may
+/// be left open. A `limit` of `None` returns every matching observation.
+#[derive(Debug, Clone, Default)]
+pub struct ObservationRange {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub serial_number: Option<String>,
+    pub order: SortOrder,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl ObservationRange {
+    pub fn new(from: Option<u64>, to: Option<u64>) -> Self {
+        Self {
+            from,
+            to,
+            ..Default::default()
+        }
+    }
+
+    /// The statement to run for this range. Its parameters are given by `bounds`.
+    pub fn query(&self) -> &'static str {
+        match self.order {
+            SortOrder::Ascending => QUERY_SELECT_OBSERVATIONS_RANGE_ASC,
+            SortOrder::Descending => QUERY_SELECT_OBSERVATIONS_RANGE_DESC,
+        }
+    }
+
+    /// The `from`, `to`, `limit` and `offset` parameters, with open ends filled in with values
+    /// SQLite accepts. A negative limit means no limit.
+    pub fn bounds(&self) -> (i64, i64, i64, i64) {
+        (
+            self.from.map_or(0, |from| from as i64),
+            self.to.map_or(i64::MAX, |to| to as i64),
+            self.limit.map_or(-1, |limit| limit as i64),
+            self.offset as i64,
+        )
+    }
+}
+
+pub const QUERY_INSERT_RAPID_WIND: &str = "INSERT INTO rapid_wind (
+    time_epoch,
+    wind_speed,
+    wind_direction,
+    serial_number
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3,
+    ?4
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
+
+pub const QUERY_CREATE_TABLE_RAPID_WIND: &str = "CREATE TABLE IF NOT EXISTS rapid_wind (
+    id INTEGER PRIMARY KEY,
+    time_epoch INTEGER,
+    wind_speed REAL,
+    wind_direction INTEGER,
+    serial_number TEXT
+)";
+
+pub const QUERY_CREATE_INDEX_RAPID_WIND_TIME: &str =
+    "CREATE INDEX IF NOT EXISTS rapid_wind_time_epoch ON rapid_wind (time_epoch)";
+
+pub const QUERY_SELECT_RAPID_WIND_RANGE: &str = "SELECT * FROM rapid_wind
+WHERE time_epoch >= ?1 AND time_epoch < ?2
+ORDER BY time_epoch ASC";
+
+pub const QUERY_SELECT_RAPID_WIND_AFTER_ID: &str = "SELECT * FROM rapid_wind
+WHERE id > ?1
+ORDER BY id ASC";
+
+pub const QUERY_INSERT_LIGHTNING_STRIKE: &str = "INSERT INTO lightning_strike (
+    time_epoch,
+    distance,
+    energy,
+    serial_number
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3,
+    ?4
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
+
+pub const QUERY_CREATE_TABLE_LIGHTNING_STRIKE: &str =
+    "CREATE TABLE IF NOT EXISTS lightning_strike (
+    id INTEGER PRIMARY KEY,
+    time_epoch INTEGER,
+    distance INTEGER,
+    energy INTEGER,
+    serial_number TEXT
+)";
+
+pub const QUERY_SELECT_LIGHTNING_STRIKE_RANGE: &str = "SELECT * FROM lightning_strike
+WHERE time_epoch >= ?1 AND time_epoch < ?2
+ORDER BY time_epoch ASC";
+
+pub const QUERY_SELECT_LIGHTNING_STRIKE_AFTER_ID: &str = "SELECT * FROM lightning_strike
+WHERE id > ?1
+ORDER BY id ASC";
+
+pub const QUERY_INSERT_RAIN_START: &str = "INSERT INTO rain_start (
+    time_epoch,
+    serial_number
+)
+VALUES (
+    ?1,
+    ?2
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
+
+pub const QUERY_CREATE_TABLE_RAIN_START: &str = "CREATE TABLE IF NOT EXISTS rain_start (
+    id INTEGER PRIMARY KEY,
+    time_epoch INTEGER,
+    serial_number TEXT
+)";
+
+pub const QUERY_SELECT_RAIN_START_RANGE: &str = "SELECT * FROM rain_start
+WHERE time_epoch >= ?1 AND time_epoch < ?2
+ORDER BY time_epoch ASC";
+
+pub const QUERY_SELECT_RAIN_START_AFTER_ID: &str = "SELECT * FROM rain_start
+WHERE id > ?1
+ORDER BY id ASC";
+
+pub const QUERY_INSERT_DEVICE_STATUS: &str = "INSERT INTO device_status (
+    time_epoch,
+    serial_number,
+    hub_sn,
+    uptime,
+    voltage,
+    firmware_revision,
+    rssi,
+    hub_rssi,
+    sensor_status,
+    debug
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3,
+    ?4,
+    ?5,
+    ?6,
+    ?7,
+    ?8,
+    ?9,
+    ?10
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
+
+pub const QUERY_CREATE_TABLE_DEVICE_STATUS: &str = "CREATE TABLE IF NOT EXISTS device_status (
+    id INTEGER PRIMARY KEY,
+    time_epoch INTEGER,
+    serial_number TEXT,
+    hub_sn TEXT,
+    uptime INTEGER,
+    voltage REAL,
+    firmware_revision INTEGER,
+    rssi INTEGER,
+    hub_rssi INTEGER,
+    sensor_status INTEGER,
+    debug INTEGER
+)";
+
+pub const QUERY_SELECT_DEVICE_STATUS_RANGE: &str = "SELECT * FROM device_status
+WHERE time_epoch >= ?1 AND time_epoch < ?2
+ORDER BY time_epoch ASC";
+
+pub const QUERY_SELECT_DEVICE_STATUS_AFTER_ID: &str = "SELECT * FROM device_status
+WHERE id > ?1
+ORDER BY id ASC";
+
+pub const QUERY_INSERT_HUB_STATUS: &str = "INSERT INTO hub_status (
+    time_epoch,
+    serial_number,
+    firmware_revision,
+    uptime,
+    rssi,
+    reset_flags,
+    seq,
+    radio_version,
+    reboot_count,
+    bus_error_count,
+    radio_status,
+    radio_network_id
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3,
+    ?4,
+    ?5,
+    ?6,
+    ?7,
+    ?8,
+    ?9,
+    ?10,
+    ?11,
+    ?12
+)
+ON CONFLICT (serial_number, time_epoch) DO NOTHING
+RETURNING id";
+
+pub const QUERY_CREATE_TABLE_HUB_STATUS: &str = "CREATE TABLE IF NOT EXISTS hub_status (
+    id INTEGER PRIMARY KEY,
+    time_epoch INTEGER,
+    serial_number TEXT,
+    firmware_revision TEXT,
+    uptime INTEGER,
+    rssi INTEGER,
+    reset_flags TEXT,
+    seq INTEGER,
+    radio_version INTEGER,
+    reboot_count INTEGER,
+    bus_error_count INTEGER,
+    radio_status INTEGER,
+    radio_network_id INTEGER
+)";
+
+pub const QUERY_SELECT_HUB_STATUS_RANGE: &str = "SELECT * FROM hub_status
+WHERE time_epoch >= ?1 AND time_epoch < ?2
+ORDER BY time_epoch ASC";
+
+pub const QUERY_SELECT_HUB_STATUS_AFTER_ID: &str = "SELECT * FROM hub_status
+WHERE id > ?1
+ORDER BY id ASC";
+
+// Rows stored before serial numbers were recorded have none, and can't be told apart from another
+// device's rows at the same time, so they're left alone. The unique indexes treat NULLs as
+// distinct, so they don't need to be deduplicated.
+pub const QUERY_DELETE_DUPLICATE_OBSERVATION: &str = "DELETE FROM observation
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM observation WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_OBSERVATION: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS observation_serial_time ON observation (serial_number, time_epoch)";
+
+pub const QUERY_DELETE_DUPLICATE_RAPID_WIND: &str = "DELETE FROM rapid_wind
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM rapid_wind WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_RAPID_WIND: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS rapid_wind_serial_time ON rapid_wind (serial_number, time_epoch)";
+
+pub const QUERY_DELETE_DUPLICATE_LIGHTNING_STRIKE: &str =
+    "DELETE FROM lightning_strike
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM lightning_strike WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_LIGHTNING_STRIKE: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS lightning_strike_serial_time ON lightning_strike (serial_number, time_epoch)";
+
+pub const QUERY_DELETE_DUPLICATE_RAIN_START: &str = "DELETE FROM rain_start
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM rain_start WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_RAIN_START: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS rain_start_serial_time ON rain_start (serial_number, time_epoch)";
+
+pub const QUERY_DELETE_DUPLICATE_DEVICE_STATUS: &str = "DELETE FROM device_status
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM device_status WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_DEVICE_STATUS: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS device_status_serial_time ON device_status (serial_number, time_epoch)";
+
+pub const QUERY_DELETE_DUPLICATE_HUB_STATUS: &str = "DELETE FROM hub_status
+WHERE serial_number IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM hub_status WHERE serial_number IS NOT NULL GROUP BY serial_number, time_epoch
+)";
+
+pub const QUERY_CREATE_UNIQUE_INDEX_HUB_STATUS: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS hub_status_serial_time ON hub_status (serial_number, time_epoch)";
+
+/// Rebuilds a table with `AUTOINCREMENT` ids, which are never reused, even after the newest rows
+/// are deleted, so rows can be followed by id. Dropping the old table drops its indexes too.
+macro_rules! autoincrement_queries {
+    ($table:literal, $columns:literal, $names:literal) => {
+        (
+            concat!(
+                "CREATE TABLE ",
+                $table,
+                "_autoincrement (\n    id INTEGER PRIMARY KEY AUTOINCREMENT,",
+                $columns,
+                "\n)"
+            ),
+            concat!(
+                "INSERT INTO ",
+                $table,
+                "_autoincrement (id, ",
+                $names,
+                ") SELECT id, ",
+                $names,
+                " FROM ",
+                $table
+            ),
+            concat!("DROP TABLE ", $table),
+            concat!("ALTER TABLE ", $table, "_autoincrement RENAME TO ", $table),
+        )
+    };
+}
+
+const AUTOINCREMENT_OBSERVATION_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "observation",
+    "
+    time_epoch INTEGER,
+    wind_lull REAL,
+    wind_avg REAL,
+    wind_gust REAL,
+    wind_direction INTEGER,
+    wind_sample_interval INTEGER,
+    station_pressure REAL,
+    air_temp REAL,
+    relative_humidity REAL,
+    illuminance INTEGER,
+    uv_index REAL,
+    solar_radiation INTEGER,
+    rain_over_prev_minute REAL,
+    precip_type INTEGER,
+    lightning_avg_distance INTEGER,
+    lightning_strike_count INTEGER,
+    battery_voltage REAL,
+    report_interval INTEGER,
+    serial_number TEXT,
+    hub_sn TEXT",
+    "time_epoch, wind_lull, wind_avg, wind_gust, wind_direction, wind_sample_interval, \
+station_pressure, air_temp, relative_humidity, illuminance, uv_index, solar_radiation, \
+rain_over_prev_minute, precip_type, lightning_avg_distance, lightning_strike_count, \
+battery_voltage, report_interval, serial_number, hub_sn"
+);
+const AUTOINCREMENT_RAPID_WIND_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "rapid_wind",
+    "
+    time_epoch INTEGER,
+    wind_speed REAL,
+    wind_direction INTEGER,
+    serial_number TEXT",
+    "time_epoch, wind_speed, wind_direction, serial_number"
+);
+const AUTOINCREMENT_LIGHTNING_STRIKE_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "lightning_strike",
+    "
+    time_epoch INTEGER,
+    distance INTEGER,
+    energy INTEGER,
+    serial_number TEXT",
+    "time_epoch, distance, energy, serial_number"
+);
+const AUTOINCREMENT_RAIN_START_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "rain_start",
+    "
+    time_epoch INTEGER,
+    serial_number TEXT",
+    "time_epoch, serial_number"
+);
+const AUTOINCREMENT_DEVICE_STATUS_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "device_status",
+    "
+    time_epoch INTEGER,
+    serial_number TEXT,
+    hub_sn TEXT,
+    uptime INTEGER,
+    voltage REAL,
+    firmware_revision INTEGER,
+    rssi INTEGER,
+    hub_rssi INTEGER,
+    sensor_status INTEGER,
+    debug INTEGER",
+    "time_epoch, serial_number, hub_sn, uptime, voltage, firmware_revision, rssi, hub_rssi, \
+sensor_status, debug"
+);
+const AUTOINCREMENT_HUB_STATUS_QUERIES: (&str, &str, &str, &str) = autoincrement_queries!(
+    "hub_status",
+    "
+    time_epoch INTEGER,
+    serial_number TEXT,
+    firmware_revision TEXT,
+    uptime INTEGER,
+    rssi INTEGER,
+    reset_flags TEXT,
+    seq INTEGER,
+    radio_version INTEGER,
+    reboot_count INTEGER,
+    bus_error_count INTEGER,
+    radio_status INTEGER,
+    radio_network_id INTEGER",
+    "time_epoch, serial_number, firmware_revision, uptime, rssi, reset_flags, seq, \
+radio_version, reboot_count, bus_error_count, radio_status, radio_network_id"
+);
+
+/// The hourly and daily rollup tables share a layout, so their statements are generated from the
+/// table name. Means are stored as running sums and divided out when selected.
+macro_rules! rollup_queries {
+    ($table:literal) => {
+        (
+            concat!(
+                "CREATE TABLE IF NOT EXISTS ",
+                $table,
+                " (
+    period_start INTEGER,
+    serial_number TEXT,
+    sample_count INTEGER,
+    air_temp_min REAL,
+    air_temp_max REAL,
+    air_temp_sum REAL,
+    relative_humidity_min REAL,
+    relative_humidity_max REAL,
+    relative_humidity_sum REAL,
+    station_pressure_min REAL,
+    station_pressure_max REAL,
+    station_pressure_sum REAL,
+    wind_avg_sum REAL,
+    wind_gust_max REAL,
+    wind_gust_direction INTEGER,
+    rain_total REAL,
+    uv_index_max REAL,
+    solar_energy REAL,
+    lightning_strike_count INTEGER,
+    PRIMARY KEY (serial_number, period_start)
+)"
+            ),
+            concat!(
+                "INSERT INTO ",
+                $table,
+                " (
+    period_start,
+    serial_number,
+    sample_count,
+    air_temp_min,
+    air_temp_max,
+    air_temp_sum,
+    relative_humidity_min,
+    relative_humidity_max,
+    relative_humidity_sum,
+    station_pressure_min,
+    station_pressure_max,
+    station_pressure_sum,
+    wind_avg_sum,
+    wind_gust_max,
+    wind_gust_direction,
+    rain_total,
+    uv_index_max,
+    solar_energy,
+    lightning_strike_count
+)
+VALUES (
+    ?1,
+    ?2,
+    1,
+    ?3,
+    ?3,
+    ?3,
+    ?4,
+    ?4,
+    ?4,
+    ?5,
+    ?5,
+    ?5,
+    ?6,
+    ?7,
+    ?8,
+    ?9,
+    ?10,
+    ?11,
+    ?12
+)
+ON CONFLICT (serial_number, period_start) DO UPDATE SET
+    sample_count = sample_count + 1,
+    air_temp_min = MIN(air_temp_min, excluded.air_temp_min),
+    air_temp_max = MAX(air_temp_max, excluded.air_temp_max),
+    air_temp_sum = air_temp_sum + excluded.air_temp_sum,
+    relative_humidity_min = MIN(relative_humidity_min, excluded.relative_humidity_min),
+    relative_humidity_max = MAX(relative_humidity_max, excluded.relative_humidity_max),
+    relative_humidity_sum = relative_humidity_sum + excluded.relative_humidity_sum,
+    station_pressure_min = MIN(station_pressure_min, excluded.station_pressure_min),
+    station_pressure_max = MAX(station_pressure_max, excluded.station_pressure_max),
+    station_pressure_sum = station_pressure_sum + excluded.station_pressure_sum,
+    wind_avg_sum = wind_avg_sum + excluded.wind_avg_sum,
+    wind_gust_direction = CASE
+        WHEN excluded.wind_gust_max > wind_gust_max THEN excluded.wind_gust_direction
+        ELSE wind_gust_direction
+    END,
+    wind_gust_max = MAX(wind_gust_max, excluded.wind_gust_max),
+    rain_total = rain_total + excluded.rain_total,
+    uv_index_max = MAX(uv_index_max, excluded.uv_index_max),
+    solar_energy = solar_energy + excluded.solar_energy,
+    lightning_strike_count = lightning_strike_count + excluded.lightning_strike_count"
+            ),
+            concat!(
+                "SELECT
+    period_start,
+    serial_number,
+    sample_count,
+    air_temp_min,
+    air_temp_max,
+    air_temp_sum / sample_count,
+    relative_humidity_min,
+    relative_humidity_max,
+    relative_humidity_sum / sample_count,
+    station_pressure_min,
+    station_pressure_max,
+    station_pressure_sum / sample_count,
+    wind_avg_sum / sample_count,
+    wind_gust_max,
+    wind_gust_direction,
+    rain_total,
+    uv_index_max,
+    solar_energy,
+    lightning_strike_count
+FROM ",
+                $table,
+                "
+WHERE period_start >= ?1 AND period_start < ?2 AND (?3 IS NULL OR instr('/' || serial_number || '/', '/' || ?3 || '/') > 0)
+ORDER BY period_start ASC, serial_number ASC"
+            ),
+        )
+    };
+}
+
+const ROLLUP_HOURLY_QUERIES: (&str, &str, &str) = rollup_queries!("rollup_hourly");
+const ROLLUP_DAILY_QUERIES: (&str, &str, &str) = rollup_queries!("rollup_daily");
+
+pub const QUERY_CREATE_TABLE_ROLLUP_HOURLY: &str = ROLLUP_HOURLY_QUERIES.0;
+pub const QUERY_UPSERT_ROLLUP_HOURLY: &str = ROLLUP_HOURLY_QUERIES.1;
+pub const QUERY_SELECT_ROLLUP_HOURLY_RANGE: &str = ROLLUP_HOURLY_QUERIES.2;
+
+pub const QUERY_CREATE_TABLE_ROLLUP_DAILY: &str = ROLLUP_DAILY_QUERIES.0;
+pub const QUERY_UPSERT_ROLLUP_DAILY: &str = ROLLUP_DAILY_QUERIES.1;
+pub const QUERY_SELECT_ROLLUP_DAILY_RANGE: &str = ROLLUP_DAILY_QUERIES.2;
+
+pub const QUERY_DELETE_ROLLUP_HOURLY_FROM: &str =
+    "DELETE FROM rollup_hourly WHERE period_start >= ?1";
+
+pub const QUERY_DELETE_ROLLUP_DAILY_FROM: &str =
+    "DELETE FROM rollup_daily WHERE period_start >= ?1";
+
+/**
+Sums rain over each of the periods in `RainPeriods`, from ?1 last hour, ?2 last 24 hours, ?3 today,
+?4 yesterday, ?5 this month and ?6 this year up to ?7 now, along with the latest minute's rain if it
+was reported since ?8.
+
+?9 is the serial number to sum the rain of. If it's null, the station that reported the latest
+observation is used, so the rain of several stations is never added together.
+*/
+pub const QUERY_SELECT_RAIN_TOTALS: &str = "WITH station AS (
+    SELECT COALESCE(?9, (SELECT serial_number FROM observation ORDER BY id DESC LIMIT 1)) AS serial
+)
+SELECT
+    COALESCE(SUM(CASE WHEN time_epoch >= ?1 THEN rain_over_prev_minute END), 0) AS last_hour,
+    COALESCE(SUM(CASE WHEN time_epoch >= ?2 THEN rain_over_prev_minute END), 0) AS last_24h,
+    COALESCE(SUM(CASE WHEN time_epoch >= ?3 THEN rain_over_prev_minute END), 0) AS today,
+    COALESCE(SUM(CASE WHEN time_epoch >= ?4 AND time_epoch < ?3 THEN rain_over_prev_minute END), 0)
+        AS yesterday,
+    COALESCE(SUM(CASE WHEN time_epoch >= ?5 THEN rain_over_prev_minute END), 0) AS month,
+    COALESCE(SUM(CASE WHEN time_epoch >= ?6 THEN rain_over_prev_minute END), 0) AS year,
+    COALESCE((SELECT rain_over_prev_minute FROM observation, station
+        WHERE time_epoch >= ?8 AND time_epoch < ?7 AND (serial IS NULL OR instr('/' || serial_number || '/', '/' || serial || '/') > 0)
+        ORDER BY time_epoch DESC LIMIT 1), 0) AS latest_minute
+FROM observation, station
+WHERE time_epoch >= MIN(?2, ?4, ?6) AND time_epoch < ?7 AND (serial IS NULL OR instr('/' || serial_number || '/', '/' || serial || '/') > 0)";
+
+// Row ids only grow, since the tables of events use AUTOINCREMENT ids, which are never reused.
+pub const QUERY_SELECT_LATEST_IDS: &str = "SELECT
+    (SELECT COALESCE(MAX(id), 0) FROM observation),
+    (SELECT COALESCE(MAX(id), 0) FROM rapid_wind),
+    (SELECT COALESCE(MAX(id), 0) FROM lightning_strike),
+    (SELECT COALESCE(MAX(id), 0) FROM rain_start),
+    (SELECT COALESCE(MAX(id), 0) FROM device_status),
+    (SELECT COALESCE(MAX(id), 0) FROM hub_status)";
+
+pub const QUERY_SELECT_OLDEST_OBSERVATION_TIME: &str = "SELECT MIN(time_epoch) FROM observation
+WHERE ?1 IS NULL OR instr('/' || serial_number || '/', '/' || ?1 || '/') > 0";
+
+pub const QUERY_SELECT_ROLLUP_DAILY_RAIN: &str = "SELECT COALESCE(SUM(rain_total), 0)
+FROM rollup_daily
+WHERE period_start >= ?1 AND period_start < ?2 AND (?3 IS NULL OR instr('/' || serial_number || '/', '/' || ?3 || '/') > 0)";
+
+/// Generates the statements that count and delete the rows of a table older than a cutoff.
+macro_rules! prune_queries {
+    ($table:literal, $time_column:literal) => {
+        (
+            concat!(
+                "SELECT COUNT(*) FROM ",
+                $table,
+                " WHERE ",
+                $time_column,
+                " < ?1"
+            ),
+            concat!("DELETE FROM ", $table, " WHERE ", $time_column, " < ?1"),
+        )
+    };
+}
+
+const PRUNE_OBSERVATION_QUERIES: (&str, &str) = prune_queries!("observation", "time_epoch");
+const PRUNE_RAPID_WIND_QUERIES: (&str, &str) = prune_queries!("rapid_wind", "time_epoch");
+const PRUNE_LIGHTNING_STRIKE_QUERIES: (&str, &str) =
+    prune_queries!("lightning_strike", "time_epoch");
+const PRUNE_RAIN_START_QUERIES: (&str, &str) = prune_queries!("rain_start", "time_epoch");
+const PRUNE_DEVICE_STATUS_QUERIES: (&str, &str) = prune_queries!("device_status", "time_epoch");
+const PRUNE_HUB_STATUS_QUERIES: (&str, &str) = prune_queries!("hub_status", "time_epoch");
+const PRUNE_ROLLUP_HOURLY_QUERIES: (&str, &str) = prune_queries!("rollup_hourly", "period_start");
+const PRUNE_ROLLUP_DAILY_QUERIES: (&str, &str) = prune_queries!("rollup_daily", "period_start");
+
+pub const QUERY_COUNT_PRUNE_OBSERVATION: &str = PRUNE_OBSERVATION_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_OBSERVATION: &str = PRUNE_OBSERVATION_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_RAPID_WIND: &str = PRUNE_RAPID_WIND_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_RAPID_WIND: &str = PRUNE_RAPID_WIND_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_LIGHTNING_STRIKE: &str = PRUNE_LIGHTNING_STRIKE_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_LIGHTNING_STRIKE: &str = PRUNE_LIGHTNING_STRIKE_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_RAIN_START: &str = PRUNE_RAIN_START_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_RAIN_START: &str = PRUNE_RAIN_START_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_DEVICE_STATUS: &str = PRUNE_DEVICE_STATUS_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_DEVICE_STATUS: &str = PRUNE_DEVICE_STATUS_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_HUB_STATUS: &str = PRUNE_HUB_STATUS_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_HUB_STATUS: &str = PRUNE_HUB_STATUS_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_ROLLUP_HOURLY: &str = PRUNE_ROLLUP_HOURLY_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_ROLLUP_HOURLY: &str = PRUNE_ROLLUP_HOURLY_QUERIES.1;
+pub const QUERY_COUNT_PRUNE_ROLLUP_DAILY: &str = PRUNE_ROLLUP_DAILY_QUERIES.0;
+pub const QUERY_DELETE_PRUNE_ROLLUP_DAILY: &str = PRUNE_ROLLUP_DAILY_QUERIES.1;
+
+/// Records waiting to be uploaded, as the JSON body to `POST` to `path` on the upload server.
+pub const QUERY_CREATE_TABLE_OUTBOX: &str = "CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_epoch INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT
+)";
+
+pub const QUERY_INSERT_OUTBOX: &str = "INSERT INTO outbox (
+    path,
+    body,
+    created_epoch
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3
+)";
+
+pub const QUERY_SELECT_OUTBOX: &str = "SELECT id, path, body, created_epoch, attempts, last_error
+FROM outbox
+ORDER BY id ASC LIMIT ?1";
+
+pub const QUERY_COUNT_OUTBOX: &str = "SELECT COUNT(*) FROM outbox";
+
+pub const QUERY_DELETE_OUTBOX: &str = "DELETE FROM outbox WHERE id = ?1";
+
+pub const QUERY_UPDATE_OUTBOX_FAILED: &str =
+    "UPDATE outbox SET attempts = attempts + 1, last_error = ?2 WHERE id = ?1";
+
+pub const QUERY_CREATE_TABLE_SCHEMA_VERSION: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    description TEXT,
+    applied_epoch INTEGER
+)";
+
+pub const QUERY_SELECT_SCHEMA_VERSION: &str =
+    "SELECT COALESCE(MAX(version), 0) AS version FROM schema_version";
+
+pub const QUERY_INSERT_SCHEMA_VERSION: &str = "INSERT INTO schema_version (
+    version,
+    description,
+    applied_epoch
+)
+VALUES (
+    ?1,
+    ?2,
+    ?3
+)";
+
+/// One step in the history of the database schema. Steps are applied in order of `version`, each
+/// one atomically, and recorded in the `schema_version` table once applied. Never edit a step that
+/// has shipped; add a new one instead.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+    /// Whether the step is only for the listener's SQLite databases, for tables that nothing
+    /// maintains or reads in the worker's D1 database. The worker records these steps as applied
+    /// without running them, so both share one sequence of versions.
+    pub sqlite_only: bool,
+}
+
+/**
+Every migration, in the order they must be applied. The first few use `IF NOT EXISTS` so that
+databases created before migrations existed are adopted as-is.
+
+The rollup tables (version 7) and the upload outbox (version 9) are SQLite-only: the listener
+maintains rollups as it stores observations and queues uploads to the worker, neither of which
+happens in D1. D1 databases migrated before this was marked have these tables, empty. So is
+making ids never reused (version 10), which only `watch --db` relies on.
+*/
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create observation table",
+        statements: &[QUERY_CREATE_TABLE_OBSERVATION],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 2,
+        description: "Create rapid_wind table",
+        statements: &[
+            QUERY_CREATE_TABLE_RAPID_WIND,
+            QUERY_CREATE_INDEX_RAPID_WIND_TIME,
+        ],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 3,
+        description: "Create lightning_strike and rain_start tables",
+        statements: &[
+            QUERY_CREATE_TABLE_LIGHTNING_STRIKE,
+            QUERY_CREATE_TABLE_RAIN_START,
+        ],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 4,
+        description: "Create device_status and hub_status tables",
+        statements: &[
+            QUERY_CREATE_TABLE_DEVICE_STATUS,
+            QUERY_CREATE_TABLE_HUB_STATUS,
+        ],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 5,
+        description: "Add serial numbers to observation",
+        statements: &[
+            QUERY_ALTER_OBSERVATION_ADD_SERIAL_NUMBER,
+            QUERY_ALTER_OBSERVATION_ADD_HUB_SN,
+        ],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 6,
+        description: "Index observation by time",
+        statements: &[QUERY_CREATE_INDEX_OBSERVATION_TIME],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 7,
+        description: "Create rollup_hourly and rollup_daily tables",
+        statements: &[
+            QUERY_CREATE_TABLE_ROLLUP_HOURLY,
+            QUERY_CREATE_TABLE_ROLLUP_DAILY,
+        ],
+        sqlite_only: true,
+    },
+    Migration {
+        version: 8,
+        description: "Deduplicate by serial number and time",
+        statements: &[
+            QUERY_DELETE_DUPLICATE_OBSERVATION,
+            QUERY_CREATE_UNIQUE_INDEX_OBSERVATION,
+            QUERY_DELETE_DUPLICATE_RAPID_WIND,
+            QUERY_CREATE_UNIQUE_INDEX_RAPID_WIND,
+            QUERY_DELETE_DUPLICATE_LIGHTNING_STRIKE,
+            QUERY_CREATE_UNIQUE_INDEX_LIGHTNING_STRIKE,
+            QUERY_DELETE_DUPLICATE_RAIN_START,
+            QUERY_CREATE_UNIQUE_INDEX_RAIN_START,
+            QUERY_DELETE_DUPLICATE_DEVICE_STATUS,
+            QUERY_CREATE_UNIQUE_INDEX_DEVICE_STATUS,
+            QUERY_DELETE_DUPLICATE_HUB_STATUS,
+            QUERY_CREATE_UNIQUE_INDEX_HUB_STATUS,
+        ],
+        sqlite_only: false,
+    },
+    Migration {
+        version: 9,
+        description: "Create outbox table",
+        statements: &[QUERY_CREATE_TABLE_OUTBOX],
+        sqlite_only: true,
+    },
+    Migration {
+        version: 10,
+        description: "Never reuse row ids of stored events",
+        statements: &[
+            AUTOINCREMENT_OBSERVATION_QUERIES.0,
+            AUTOINCREMENT_OBSERVATION_QUERIES.1,
+            AUTOINCREMENT_OBSERVATION_QUERIES.2,
+            AUTOINCREMENT_OBSERVATION_QUERIES.3,
+            QUERY_CREATE_UNIQUE_INDEX_OBSERVATION,
+            AUTOINCREMENT_RAPID_WIND_QUERIES.0,
+            AUTOINCREMENT_RAPID_WIND_QUERIES.1,
+            AUTOINCREMENT_RAPID_WIND_QUERIES.2,
+            AUTOINCREMENT_RAPID_WIND_QUERIES.3,
+            QUERY_CREATE_INDEX_RAPID_WIND_TIME,
+            QUERY_CREATE_UNIQUE_INDEX_RAPID_WIND,
+            AUTOINCREMENT_LIGHTNING_STRIKE_QUERIES.0,
+            AUTOINCREMENT_LIGHTNING_STRIKE_QUERIES.1,
+            AUTOINCREMENT_LIGHTNING_STRIKE_QUERIES.2,
+            AUTOINCREMENT_LIGHTNING_STRIKE_QUERIES.3,
+            QUERY_CREATE_UNIQUE_INDEX_LIGHTNING_STRIKE,
+            AUTOINCREMENT_RAIN_START_QUERIES.0,
+            AUTOINCREMENT_RAIN_START_QUERIES.1,
+            AUTOINCREMENT_RAIN_START_QUERIES.2,
+            AUTOINCREMENT_RAIN_START_QUERIES.3,
+            QUERY_CREATE_UNIQUE_INDEX_RAIN_START,
+            AUTOINCREMENT_DEVICE_STATUS_QUERIES.0,
+            AUTOINCREMENT_DEVICE_STATUS_QUERIES.1,
+            AUTOINCREMENT_DEVICE_STATUS_QUERIES.2,
+            AUTOINCREMENT_DEVICE_STATUS_QUERIES.3,
+            QUERY_CREATE_UNIQUE_INDEX_DEVICE_STATUS,
+            AUTOINCREMENT_HUB_STATUS_QUERIES.0,
+            AUTOINCREMENT_HUB_STATUS_QUERIES.1,
+            AUTOINCREMENT_HUB_STATUS_QUERIES.2,
+            AUTOINCREMENT_HUB_STATUS_QUERIES.3,
+            QUERY_CREATE_UNIQUE_INDEX_HUB_STATUS,
+        ],
+        // Only `watch --db` follows rows by id, and only in SQLite.
+        sqlite_only: true,
+    },
+];
+
+/// The migrations that still need to be applied to a database at `current_version`.
+pub fn pending_migrations(current_version: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(move |migration| migration.version > current_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn pending_migrations_start_after_the_current_version() {
+        let latest = MIGRATIONS.last().unwrap().version;
+
+        assert_eq!(pending_migrations(0).count(), MIGRATIONS.len());
+        assert_eq!(
+            pending_migrations(3).map(|m| m.version).collect::<Vec<_>>(),
+            (4..=latest).collect::<Vec<_>>()
+        );
+        assert_eq!(pending_migrations(latest).count(), 0);
+        assert_eq!(pending_migrations(latest + 1).count(), 0);
+    }
+
+    #[test]
+    fn rollups_and_the_outbox_are_sqlite_only() {
+        for migration in MIGRATIONS.iter().filter(|migration| !migration.sqlite_only) {
+            for statement in migration.statements {
+                assert!(!statement.contains("rollup_") && !statement.contains("outbox"));
+            }
+        }
+    }
+}
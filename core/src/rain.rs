@@ -0,0 +1,237 @@
+use chrono::{Datelike, Duration, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::units::{FormatWithUnits, Precipitation, PrecipitationUnit, UnitPreferences};
+
+/// How long the latest observation counts toward the current rain rate. Older than this, the
+/// station has stopped reporting and the rate is unknown, so it's reported as zero.
+pub const RAIN_RATE_MAX_AGE: u64 = 5 * 60;
+
+/**
+The start of each period rain is accumulated over, as Unix epochs, all ending at `end`.
+
+Days, months and years are counted in the station's time zone, and start at `day_start_hour` rather
+than midnight, so that totals can match services that reset at 9 AM. With a 9 AM reset, "today"
+at 8 AM is the 24 hours since 9 AM yesterday, and the month starts at 9 AM on the first.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RainPeriods {
+    pub last_hour: u64,
+    pub last_24h: u64,
+    pub today: u64,
+    pub yesterday: u64,
+    pub month: u64,
+    pub year: u64,
+    pub end: u64,
+}
+
+/// The epoch of `hour` o'clock on `date` in `tz`. If that time is skipped by a DST change, the
+/// next hour is used instead.
+pub(crate) fn local_start<Tz: TimeZone>(tz: &Tz, date: NaiveDate, hour: u32) -> u64 {
+    let start = date.and_hms_opt(hour, 0, 0).unwrap();
+
+    tz.from_local_datetime(&start)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(start + Duration::hours(1)))
+                .earliest()
+        })
+        .map_or_else(
+            || tz.from_utc_datetime(&start).timestamp(),
+            |time| time.timestamp(),
+        ) as u64
+}
+
+impl RainPeriods {
+    pub fn new<Tz: TimeZone>(now_epoch: u64, day_start_hour: u32, tz: &Tz) -> Self {
+        let day_start_hour = day_start_hour.min(23);
+        let now = tz.timestamp_opt(now_epoch as i64, 0).unwrap();
+
+        // The date the current rain day started on, which is yesterday's date before the reset.
+        let mut date = now.date_naive();
+
+        if local_start(tz, date, day_start_hour) > now_epoch {
+            date = date.pred_opt().unwrap();
+        }
+
+        Self {
+            last_hour: now_epoch.saturating_sub(3600),
+            last_24h: now_epoch.saturating_sub(24 * 3600),
+            today: local_start(tz, date, day_start_hour),
+            yesterday: local_start(tz, date.pred_opt().unwrap(), day_start_hour),
+            month: local_start(tz, date.with_day(1).unwrap(), day_start_hour),
+            year: local_start(tz, date.with_ordinal(1).unwrap(), day_start_hour),
+            end: now_epoch + 1,
+        }
+    }
+
+    /// The oldest start, before which no rain is counted.
+    pub fn earliest(&self) -> u64 {
+        self.last_24h.min(self.yesterday).min(self.year)
+    }
+
+    /// The time after which an observation's rain counts toward the current rate.
+    pub fn rate_start(&self) -> u64 {
+        self.end.saturating_sub(RAIN_RATE_MAX_AGE)
+    }
+}
+
+/// The columns selected by `QUERY_SELECT_RAIN_TOTALS`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct RainSums {
+    pub last_hour: f32,
+    pub last_24h: f32,
+    pub today: f32,
+    pub yesterday: f32,
+    pub month: f32,
+    pub year: f32,
+    pub latest_minute: f32,
+}
+
+impl From<RainSums> for RainTotals {
+    fn from(sums: RainSums) -> Self {
+        Self {
+            rain_rate: sums.latest_minute * 60.0,
+            last_hour: sums.last_hour,
+            last_24h: sums.last_24h,
+            today: sums.today,
+            yesterday: sums.yesterday,
+            month: sums.month,
+            year: sums.year,
+        }
+    }
+}
+
+/// Rain accumulated over the periods in `RainPeriods`, in mm, and the current rate in mm/h.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct RainTotals {
+    pub rain_rate: f32,
+    pub last_hour: f32,
+    pub last_24h: f32,
+    pub today: f32,
+    pub yesterday: f32,
+    pub month: f32,
+    pub year: f32,
+}
+
+impl RainTotals {
+    /// The same totals in the given units, with the rate in those units per hour.
+    pub fn in_units(&self, units: &UnitPreferences) -> Self {
+        let depth = |value| {
+            units
+                .precipitation(Precipitation::new(value, PrecipitationUnit::Millimeters))
+                .value()
+        };
+
+        Self {
+            rain_rate: depth(self.rain_rate),
+            last_hour: depth(self.last_hour),
+            last_24h: depth(self.last_24h),
+            today: depth(self.today),
+            yesterday: depth(self.yesterday),
+            month: depth(self.month),
+            year: depth(self.year),
+        }
+    }
+}
+
+impl FormatWithUnits for RainTotals {
+    fn fmt_with_units(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        units: &UnitPreferences,
+    ) -> std::fmt::Result {
+        let depth =
+            |value| units.precipitation(Precipitation::new(value, PrecipitationUnit::Millimeters));
+
+        writeln!(f, "Rain Rate: {}/h", depth(self.rain_rate))?;
+        writeln!(f, "Last Hour: {}", depth(self.last_hour))?;
+        writeln!(f, "Last 24 Hours: {}", depth(self.last_24h))?;
+        writeln!(f, "Today: {}", depth(self.today))?;
+        writeln!(f, "Yesterday: {}", depth(self.yesterday))?;
+        writeln!(f, "This Month: {}", depth(self.month))?;
+        writeln!(f, "This Year: {}", depth(self.year))?;
+        Ok(())
+    }
+}
+
+impl Display for RainTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_units(f, &UnitPreferences::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_support::MidnightDst;
+    use chrono::FixedOffset;
+
+    fn epoch<Tz: TimeZone>(tz: &Tz, y: i32, m: u32, d: u32, h: u32) -> u64 {
+        tz.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap().timestamp() as u64
+    }
+
+    #[test]
+    fn counts_days_from_midnight() {
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = epoch(&tz, 2024, 3, 15, 14) + 600;
+        let periods = RainPeriods::new(now, 0, &tz);
+
+        assert_eq!(periods.last_hour, now - 3600);
+        assert_eq!(periods.last_24h, now - 24 * 3600);
+        assert_eq!(periods.today, epoch(&tz, 2024, 3, 15, 0));
+        assert_eq!(periods.yesterday, epoch(&tz, 2024, 3, 14, 0));
+        assert_eq!(periods.month, epoch(&tz, 2024, 3, 1, 0));
+        assert_eq!(periods.year, epoch(&tz, 2024, 1, 1, 0));
+        assert_eq!(periods.end, now + 1);
+        assert_eq!(periods.earliest(), periods.year);
+    }
+
+    #[test]
+    fn counts_days_from_a_later_reset() {
+        let tz = FixedOffset::west_opt(5 * 3600).unwrap();
+
+        // Before 9 AM on the 1st, it's still the rain day that started on the last day of the
+        // previous month, and year.
+        let now = epoch(&tz, 2024, 1, 1, 8);
+        let periods = RainPeriods::new(now, 9, &tz);
+
+        assert_eq!(periods.today, epoch(&tz, 2023, 12, 31, 9));
+        assert_eq!(periods.yesterday, epoch(&tz, 2023, 12, 30, 9));
+        assert_eq!(periods.month, epoch(&tz, 2023, 12, 1, 9));
+        assert_eq!(periods.year, epoch(&tz, 2023, 1, 1, 9));
+
+        let now = epoch(&tz, 2024, 1, 1, 9);
+        let periods = RainPeriods::new(now, 9, &tz);
+
+        assert_eq!(periods.today, now);
+        assert_eq!(periods.month, now);
+        assert_eq!(periods.year, now);
+    }
+
+    #[test]
+    fn starts_a_day_without_midnight_at_one_am() {
+        let tz = MidnightDst;
+        let now = tz
+            .with_ymd_and_hms(2024, 9, 8, 12, 0, 0)
+            .unwrap()
+            .timestamp() as u64;
+        let periods = RainPeriods::new(now, 0, &tz);
+
+        assert_eq!(periods.today, epoch(&tz, 2024, 9, 8, 1));
+        assert_eq!(periods.today - periods.yesterday, 24 * 3600);
+    }
+
+    #[test]
+    fn converts_the_latest_minute_to_a_rate() {
+        let totals = RainTotals::from(RainSums {
+            latest_minute: 0.1,
+            today: 4.0,
+            ..Default::default()
+        });
+
+        assert!((totals.rain_rate - 6.0).abs() < 0.001);
+        assert_eq!(totals.today, 4.0);
+    }
+}
@@ -0,0 +1,294 @@
+use chrono::{Local, TimeZone};
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    queries::{
+        QUERY_COUNT_PRUNE_DEVICE_STATUS, QUERY_COUNT_PRUNE_HUB_STATUS,
+        QUERY_COUNT_PRUNE_LIGHTNING_STRIKE, QUERY_COUNT_PRUNE_OBSERVATION,
+        QUERY_COUNT_PRUNE_RAIN_START, QUERY_COUNT_PRUNE_RAPID_WIND, QUERY_COUNT_PRUNE_ROLLUP_DAILY,
+        QUERY_COUNT_PRUNE_ROLLUP_HOURLY, QUERY_DELETE_PRUNE_DEVICE_STATUS,
+        QUERY_DELETE_PRUNE_HUB_STATUS, QUERY_DELETE_PRUNE_LIGHTNING_STRIKE,
+        QUERY_DELETE_PRUNE_OBSERVATION, QUERY_DELETE_PRUNE_RAIN_START,
+        QUERY_DELETE_PRUNE_RAPID_WIND, QUERY_DELETE_PRUNE_ROLLUP_DAILY,
+        QUERY_DELETE_PRUNE_ROLLUP_HOURLY,
+    },
+    util::Counted,
+};
+
+/// A table that the retention policy applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainedTable {
+    Observation,
+    RapidWind,
+    LightningStrike,
+    RainStart,
+    DeviceStatus,
+    HubStatus,
+    RollupHourly,
+    RollupDaily,
+}
+
+impl RetainedTable {
+    pub const ALL: [RetainedTable; 8] = [
+        RetainedTable::Observation,
+        RetainedTable::RapidWind,
+        RetainedTable::LightningStrike,
+        RetainedTable::RainStart,
+        RetainedTable::DeviceStatus,
+        RetainedTable::HubStatus,
+        RetainedTable::RollupHourly,
+        RetainedTable::RollupDaily,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetainedTable::Observation => "observation",
+            RetainedTable::RapidWind => "rapid_wind",
+            RetainedTable::LightningStrike => "lightning_strike",
+            RetainedTable::RainStart => "rain_start",
+            RetainedTable::DeviceStatus => "device_status",
+            RetainedTable::HubStatus => "hub_status",
+            RetainedTable::RollupHourly => "rollup_hourly",
+            RetainedTable::RollupDaily => "rollup_daily",
+        }
+    }
+
+    /// Counts the rows older than the cutoff given as `?1`.
+    pub fn count_query(&self) -> &'static str {
+        match self {
+            RetainedTable::Observation => QUERY_COUNT_PRUNE_OBSERVATION,
+            RetainedTable::RapidWind => QUERY_COUNT_PRUNE_RAPID_WIND,
+            RetainedTable::LightningStrike => QUERY_COUNT_PRUNE_LIGHTNING_STRIKE,
+            RetainedTable::RainStart => QUERY_COUNT_PRUNE_RAIN_START,
+            RetainedTable::DeviceStatus => QUERY_COUNT_PRUNE_DEVICE_STATUS,
+            RetainedTable::HubStatus => QUERY_COUNT_PRUNE_HUB_STATUS,
+            RetainedTable::RollupHourly => QUERY_COUNT_PRUNE_ROLLUP_HOURLY,
+            RetainedTable::RollupDaily => QUERY_COUNT_PRUNE_ROLLUP_DAILY,
+        }
+    }
+
+    /// Deletes the rows older than the cutoff given as `?1`.
+    pub fn delete_query(&self) -> &'static str {
+        match self {
+            RetainedTable::Observation => QUERY_DELETE_PRUNE_OBSERVATION,
+            RetainedTable::RapidWind => QUERY_DELETE_PRUNE_RAPID_WIND,
+            RetainedTable::LightningStrike => QUERY_DELETE_PRUNE_LIGHTNING_STRIKE,
+            RetainedTable::RainStart => QUERY_DELETE_PRUNE_RAIN_START,
+            RetainedTable::DeviceStatus => QUERY_DELETE_PRUNE_DEVICE_STATUS,
+            RetainedTable::HubStatus => QUERY_DELETE_PRUNE_HUB_STATUS,
+            RetainedTable::RollupHourly => QUERY_DELETE_PRUNE_ROLLUP_HOURLY,
+            RetainedTable::RollupDaily => QUERY_DELETE_PRUNE_ROLLUP_DAILY,
+        }
+    }
+}
+
+/**
+How many days of each kind of data to keep, where `None` means forever. Parsed from a
+comma-separated list of `kind=days` pairs, any of which may be omitted to keep the default:
+
+observation=30       one-minute observations
+rapid_wind=30        3-second wind samples
+events=forever       lightning strikes and rain starts
+status=30            device and hub status reports
+hourly=730           hourly rollups
+daily=forever        daily rollups
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub observation: Option<u64>,
+    pub rapid_wind: Option<u64>,
+    pub events: Option<u64>,
+    pub status: Option<u64>,
+    pub rollup_hourly: Option<u64>,
+    pub rollup_daily: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            observation: Some(30),
+            rapid_wind: Some(30),
+            events: None,
+            status: Some(30),
+            rollup_hourly: Some(730),
+            rollup_daily: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// How many days of the given table to keep, or `None` to keep it forever.
+    pub fn max_age_days(&self, table: RetainedTable) -> Option<u64> {
+        match table {
+            RetainedTable::Observation => self.observation,
+            RetainedTable::RapidWind => self.rapid_wind,
+            RetainedTable::LightningStrike | RetainedTable::RainStart => self.events,
+            RetainedTable::DeviceStatus | RetainedTable::HubStatus => self.status,
+            RetainedTable::RollupHourly => self.rollup_hourly,
+            RetainedTable::RollupDaily => self.rollup_daily,
+        }
+    }
+
+    /// The epoch before which rows of the given table should be deleted, as of `now_epoch`.
+    pub fn cutoff(&self, table: RetainedTable, now_epoch: u64) -> Option<u64> {
+        self.max_age_days(table)
+            .map(|days| now_epoch.saturating_sub(days * 24 * 3600))
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = RetentionPolicy::default();
+
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (kind, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected kind=days, got {}", pair))?;
+            let days =
+                match value.trim() {
+                    "forever" => None,
+                    days => Some(days.parse::<u64>().map_err(|_| {
+                        format!("expected a number of days or forever, got {}", days)
+                    })?),
+                };
+
+            match kind.trim() {
+                "observation" => policy.observation = days,
+                "rapid_wind" => policy.rapid_wind = days,
+                "events" => policy.events = days,
+                "status" => policy.status = days,
+                "hourly" => policy.rollup_hourly = days,
+                "daily" => policy.rollup_daily = days,
+                other => return Err(format!("unknown kind of data: {}", other)),
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+impl Display for RetentionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_days = |days: Option<u64>| match days {
+            Some(days) => days.to_string(),
+            None => "forever".to_string(),
+        };
+
+        write!(
+            f,
+            "observation={},rapid_wind={},events={},status={},hourly={},daily={}",
+            format_days(self.observation),
+            format_days(self.rapid_wind),
+            format_days(self.events),
+            format_days(self.status),
+            format_days(self.rollup_hourly),
+            format_days(self.rollup_daily)
+        )
+    }
+}
+
+/// How many rows were (or, for a dry run, would be) deleted from one table.
+#[derive(Debug, Clone)]
+pub struct PruneEntry {
+    pub table: RetainedTable,
+    pub cutoff_epoch: u64,
+    pub rows: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub entries: Vec<PruneEntry>,
+}
+
+impl PruneReport {
+    pub fn total_rows(&self) -> usize {
+        self.entries.iter().map(|entry| entry.rows).sum()
+    }
+}
+
+impl Display for PruneReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verb = if self.dry_run {
+            "Would delete"
+        } else {
+            "Deleted"
+        };
+
+        for entry in &self.entries {
+            let cutoff = Local
+                .timestamp_opt(entry.cutoff_epoch as i64, 0)
+                .unwrap()
+                .format("%B %-d, %Y at %-I:%M %p");
+
+            writeln!(
+                f,
+                "{} {} from {} (older than {})",
+                verb,
+                entry.rows.counted("row"),
+                entry.table.name(),
+                cutoff
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_kinds_and_keeps_defaults_for_the_rest() {
+        let policy: RetentionPolicy = "observation=365, daily=forever,events=90".parse().unwrap();
+
+        assert_eq!(
+            policy,
+            RetentionPolicy {
+                observation: Some(365),
+                events: Some(90),
+                rollup_daily: None,
+                ..RetentionPolicy::default()
+            }
+        );
+        assert_eq!(
+            "".parse::<RetentionPolicy>(),
+            Ok(RetentionPolicy::default())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_policies() {
+        assert!("observation".parse::<RetentionPolicy>().is_err());
+        assert!("observation=soon".parse::<RetentionPolicy>().is_err());
+        assert!("observation=-1".parse::<RetentionPolicy>().is_err());
+        assert!("weather=30".parse::<RetentionPolicy>().is_err());
+    }
+
+    #[test]
+    fn displays_in_the_form_it_parses() {
+        let policy = RetentionPolicy {
+            rapid_wind: None,
+            status: Some(7),
+            ..RetentionPolicy::default()
+        };
+
+        assert_eq!(policy.to_string().parse::<RetentionPolicy>(), Ok(policy));
+    }
+
+    #[test]
+    fn computes_cutoffs() {
+        let policy = RetentionPolicy::default();
+        let now = 100 * 24 * 3600;
+
+        assert_eq!(
+            policy.cutoff(RetainedTable::Observation, now),
+            Some(70 * 24 * 3600)
+        );
+        assert_eq!(policy.cutoff(RetainedTable::LightningStrike, now), None);
+        assert_eq!(policy.cutoff(RetainedTable::RollupHourly, now), Some(0));
+    }
+}
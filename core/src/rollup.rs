@@ -0,0 +1,246 @@
+use chrono::{DateTime, Local, LocalResult, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::{
+    queries::{
+        QUERY_SELECT_ROLLUP_DAILY_RANGE, QUERY_SELECT_ROLLUP_HOURLY_RANGE,
+        QUERY_UPSERT_ROLLUP_DAILY, QUERY_UPSERT_ROLLUP_HOURLY,
+    },
+    rain::local_start,
+    units::{
+        FormatWithUnits, Precipitation, PrecipitationUnit, Pressure, PressureUnit, Speed,
+        SpeedUnit, TempUnit, Temperature, UnitPreferences,
+    },
+    wind::WindDirection,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupPeriod {
+    Hourly,
+    Daily,
+}
+
+impl RollupPeriod {
+    /// The start of the local hour or day containing `time_epoch`.
+    pub fn period_start(&self, time_epoch: u64) -> u64 {
+        self.period_start_in(time_epoch, &Local)
+    }
+
+    /// The start of the hour or day containing `time_epoch` in `tz`. When midnight is skipped by a
+    /// DST change, the day starts at 1 AM instead.
+    pub fn period_start_in<Tz: TimeZone>(&self, time_epoch: u64, tz: &Tz) -> u64 {
+        let time = tz.timestamp_opt(time_epoch as i64, 0).unwrap();
+
+        match self {
+            RollupPeriod::Hourly => time_epoch - (time.minute() as u64 * 60 + time.second() as u64),
+            RollupPeriod::Daily => local_start(tz, time.date_naive(), 0),
+        }
+    }
+
+    /// The statement that folds one observation into this period's rollup.
+    pub fn upsert_query(&self) -> &'static str {
+        match self {
+            RollupPeriod::Hourly => QUERY_UPSERT_ROLLUP_HOURLY,
+            RollupPeriod::Daily => QUERY_UPSERT_ROLLUP_DAILY,
+        }
+    }
+
+    /// The statement that selects this period's rollups by time and serial number.
+    pub fn select_query(&self) -> &'static str {
+        match self {
+            RollupPeriod::Hourly => QUERY_SELECT_ROLLUP_HOURLY_RANGE,
+            RollupPeriod::Daily => QUERY_SELECT_ROLLUP_DAILY_RANGE,
+        }
+    }
+}
+
+/// Aggregated observations from one device over an hour or a day.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rollup {
+    pub period_start: u64,
+    pub serial_number: String,
+    pub sample_count: u32,
+    pub air_temp_min: f32,
+    pub air_temp_max: f32,
+    pub air_temp_mean: f32,
+    pub relative_humidity_min: f32,
+    pub relative_humidity_max: f32,
+    pub relative_humidity_mean: f32,
+    pub station_pressure_min: f32,
+    pub station_pressure_max: f32,
+    pub station_pressure_mean: f32,
+    pub wind_avg_mean: f32,
+    pub wind_gust_max: f32,
+    /// The direction the wind was blowing from when the strongest gust was reported.
+    pub wind_gust_direction: u16,
+    /// Total rain, in mm.
+    pub rain_total: f32,
+    pub uv_index_max: f32,
+    /// Total solar energy, in Wh/m^2.
+    pub solar_energy: f32,
+    pub lightning_strike_count: u32,
+}
+
+impl Rollup {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.period_start as i64, 0)
+    }
+}
+
+impl Rollup {
+    /// A copy of this rollup with every measurement converted to the given units, for JSON.
+    pub fn in_units(&self, units: &UnitPreferences) -> Rollup {
+        let temp = |value| {
+            units
+                .temperature(Temperature::new(value, TempUnit::C))
+                .value()
+        };
+        let speed = |value| {
+            units
+                .speed(Speed::new(value, SpeedUnit::MetersPerSecond))
+                .value()
+        };
+        let pressure = |value| {
+            units
+                .pressure(Pressure::new(value, PressureUnit::Millibars))
+                .value()
+        };
+
+        Rollup {
+            air_temp_min: temp(self.air_temp_min),
+            air_temp_max: temp(self.air_temp_max),
+            air_temp_mean: temp(self.air_temp_mean),
+            station_pressure_min: pressure(self.station_pressure_min),
+            station_pressure_max: pressure(self.station_pressure_max),
+            station_pressure_mean: pressure(self.station_pressure_mean),
+            wind_avg_mean: speed(self.wind_avg_mean),
+            wind_gust_max: speed(self.wind_gust_max),
+            rain_total: units
+                .precipitation(Precipitation::new(
+                    self.rain_total,
+                    PrecipitationUnit::Millimeters,
+                ))
+                .value(),
+            serial_number: self.serial_number.clone(),
+            ..*self
+        }
+    }
+}
+
+impl FormatWithUnits for Rollup {
+    fn fmt_with_units(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        units: &UnitPreferences,
+    ) -> std::fmt::Result {
+        let temp = |value| units.temperature(Temperature::new(value, TempUnit::C));
+        let speed = |value| units.speed(Speed::new(value, SpeedUnit::MetersPerSecond));
+        let pressure = |value| units.pressure(Pressure::new(value, PressureUnit::Millibars));
+
+        writeln!(
+            f,
+            "{} ({}, {} observations)",
+            self.get_time().unwrap().format("%B %-d, %Y at %-I:%M %p"),
+            self.serial_number,
+            self.sample_count
+        )?;
+        writeln!(
+            f,
+            "Air Temperature: {} to {}, mean {}",
+            temp(self.air_temp_min),
+            temp(self.air_temp_max),
+            temp(self.air_temp_mean)
+        )?;
+        writeln!(
+            f,
+            "Relative Humidity: {:.0}% to {:.0}%, mean {:.0}%",
+            self.relative_humidity_min, self.relative_humidity_max, self.relative_humidity_mean
+        )?;
+        writeln!(
+            f,
+            "Station Pressure: {} to {}, mean {}",
+            pressure(self.station_pressure_min),
+            pressure(self.station_pressure_max),
+            pressure(self.station_pressure_mean)
+        )?;
+        match WindDirection::from_sample(self.wind_gust_max, self.wind_gust_direction) {
+            WindDirection::From { degrees, point } => writeln!(
+                f,
+                "Wind: mean {}, max gust {} from {} ({}°)",
+                speed(self.wind_avg_mean),
+                speed(self.wind_gust_max),
+                point,
+                degrees
+            )?,
+            _ => writeln!(f, "Wind: calm")?,
+        }
+        writeln!(
+            f,
+            "Rain: {}",
+            units.precipitation(Precipitation::new(
+                self.rain_total,
+                PrecipitationUnit::Millimeters
+            ))
+        )?;
+        writeln!(f, "Max UV Index: {}", self.uv_index_max)?;
+        writeln!(f, "Solar Energy: {:.0} Wh/m^2", self.solar_energy)?;
+        writeln!(f, "Lightning Strikes: {}", self.lightning_strike_count)?;
+        Ok(())
+    }
+}
+
+impl Display for Rollup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_units(f, &UnitPreferences::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_support::{skipped_midnight, MidnightDst};
+    use chrono::{Duration, FixedOffset};
+
+    #[test]
+    fn finds_the_start_of_the_hour_and_day() {
+        let tz = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+        let time = tz
+            .with_ymd_and_hms(2024, 3, 10, 14, 25, 7)
+            .unwrap()
+            .timestamp() as u64;
+
+        assert_eq!(
+            RollupPeriod::Hourly.period_start_in(time, &tz),
+            tz.with_ymd_and_hms(2024, 3, 10, 14, 0, 0)
+                .unwrap()
+                .timestamp() as u64
+        );
+        assert_eq!(
+            RollupPeriod::Daily.period_start_in(time, &tz),
+            tz.with_ymd_and_hms(2024, 3, 10, 0, 0, 0)
+                .unwrap()
+                .timestamp() as u64
+        );
+    }
+
+    #[test]
+    fn starts_a_day_without_midnight_at_one_am() {
+        let tz = MidnightDst;
+        let one_am = tz
+            .from_local_datetime(&(skipped_midnight() + Duration::hours(1)))
+            .unwrap()
+            .timestamp() as u64;
+
+        for hours in [0, 1, 9, 22] {
+            let time = one_am + hours * 3600 + 1234;
+
+            assert_eq!(RollupPeriod::Daily.period_start_in(time, &tz), one_am);
+        }
+
+        assert_eq!(
+            RollupPeriod::Daily.period_start_in(one_am - 1, &tz),
+            one_am - 24 * 3600
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Where a station is installed. Elevation is in meters above mean sea level, at the height of
+/// the station's pressure sensor; latitude and longitude are in decimal degrees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct StationConfig {
+    #[serde(default)]
+    pub elevation: f32,
+    #[serde(default)]
+    pub latitude: f64,
+    #[serde(default)]
+    pub longitude: f64,
+    /// The local hour the rain day starts at: 0 for midnight, or 9 for the 9 AM reset used by many
+    /// weather services.
+    #[serde(default)]
+    pub rain_day_start: u32,
+}
+
+/// The exponent of the barometric formula in the standard atmosphere, R·L / (g·M).
+const BAROMETRIC_EXPONENT: f32 = 0.190_284;
+
+/// The temperature lapse rate of the standard atmosphere, in K/m.
+const LAPSE_RATE: f32 = 0.0065;
+
+/**
+Reduces station pressure in mbar to sea level, using the hypsometric equation with the current air
+temperature in °C and the standard lapse rate for the imaginary column of air below the station.
+This is what most weather services report as "pressure".
+*/
+pub fn sea_level_pressure(station_pressure: f32, elevation: f32, air_temp: f32) -> f32 {
+    let column = LAPSE_RATE * elevation;
+
+    station_pressure
+        * (1.0 - column / (air_temp + column + 273.15)).powf(-1.0 / BAROMETRIC_EXPONENT)
+}
+
+/**
+Converts station pressure in mbar to an altimeter setting in mbar, the pressure reported by
+airports (as QNH), which assumes the standard atmosphere rather than the current temperature.
+
+See the NWS "Pressure Conversion Equations", which subtract 0.3 mbar from the station pressure
+to match the ASOS algorithm.
+*/
+pub fn altimeter_setting(station_pressure: f32, elevation: f32) -> f32 {
+    let pressure = station_pressure - 0.3;
+    let ratio = 1013.25_f32.powf(BAROMETRIC_EXPONENT) * LAPSE_RATE / 288.0;
+
+    pressure
+        * (1.0 + ratio * (elevation / pressure.powf(BAROMETRIC_EXPONENT)))
+            .powf(1.0 / BAROMETRIC_EXPONENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Station pressure 1000 m up in the standard atmosphere, where it's 8.5°C.
+    const STANDARD_PRESSURE_1000M: f32 = 898.75;
+
+    #[test]
+    fn reduces_station_pressure_to_sea_level() {
+        assert_eq!(sea_level_pressure(1000.0, 0.0, 15.0), 1000.0);
+        assert!((sea_level_pressure(STANDARD_PRESSURE_1000M, 1000.0, 8.5) - 1013.25).abs() < 0.5);
+
+        // Colder air is denser, so the same station pressure reduces to a higher sea-level one.
+        assert!(
+            sea_level_pressure(STANDARD_PRESSURE_1000M, 1000.0, -10.0)
+                > sea_level_pressure(STANDARD_PRESSURE_1000M, 1000.0, 25.0)
+        );
+    }
+
+    #[test]
+    fn computes_altimeter_setting() {
+        assert!((altimeter_setting(1000.3, 0.0) - 1000.0).abs() < 0.01);
+        assert!((altimeter_setting(STANDARD_PRESSURE_1000M + 0.3, 1000.0) - 1013.25).abs() < 0.5);
+    }
+}
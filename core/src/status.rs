@@ -0,0 +1,421 @@
+use chrono::{DateTime, Local, LocalResult, TimeZone};
+use serde::de::{self, Deserializer, Unexpected};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Accepts either a JSON boolean or the zero/one integer SQLite stores booleans as.
+fn bool_from_bool_or_int<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrInt {
+        Bool(bool),
+        Int(u64),
+    }
+
+    match BoolOrInt::deserialize(deserializer)? {
+        BoolOrInt::Bool(value) => Ok(value),
+        BoolOrInt::Int(0) => Ok(false),
+        BoolOrInt::Int(1) => Ok(true),
+        BoolOrInt::Int(other) => Err(de::Error::invalid_value(
+            Unexpected::Unsigned(other),
+            &"a boolean, zero or one",
+        )),
+    }
+}
+
+/**
+The decoded `sensor_status` bitmask from a `device_status` packet:
+
+0b000000000 Sensors OK
+0b000000001 lightning failed
+0b000000010 lightning noise
+0b000000100 lightning disturber
+0b000001000 pressure failed
+0b000010000 temperature failed
+0b000100000 rh failed
+0b001000000 wind failed
+0b010000000 precip failed
+0b100000000 light/uv failed
+
+Bits above these are reserved, and are kept but otherwise ignored.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SensorFlags(u64);
+
+impl SensorFlags {
+    pub const LIGHTNING_FAILED: SensorFlags = SensorFlags(1 << 0);
+    pub const LIGHTNING_NOISE: SensorFlags = SensorFlags(1 << 1);
+    pub const LIGHTNING_DISTURBER: SensorFlags = SensorFlags(1 << 2);
+    pub const PRESSURE_FAILED: SensorFlags = SensorFlags(1 << 3);
+    pub const TEMPERATURE_FAILED: SensorFlags = SensorFlags(1 << 4);
+    pub const HUMIDITY_FAILED: SensorFlags = SensorFlags(1 << 5);
+    pub const WIND_FAILED: SensorFlags = SensorFlags(1 << 6);
+    pub const PRECIP_FAILED: SensorFlags = SensorFlags(1 << 7);
+    pub const LIGHT_UV_FAILED: SensorFlags = SensorFlags(1 << 8);
+
+    /// Every known flag, with its human-readable name.
+    const NAMED: [(SensorFlags, &'static str); 9] = [
+        (SensorFlags::LIGHTNING_FAILED, "lightning failed"),
+        (SensorFlags::LIGHTNING_NOISE, "lightning noise"),
+        (SensorFlags::LIGHTNING_DISTURBER, "lightning disturber"),
+        (SensorFlags::PRESSURE_FAILED, "pressure failed"),
+        (SensorFlags::TEMPERATURE_FAILED, "temperature failed"),
+        (SensorFlags::HUMIDITY_FAILED, "humidity failed"),
+        (SensorFlags::WIND_FAILED, "wind failed"),
+        (SensorFlags::PRECIP_FAILED, "precip failed"),
+        (SensorFlags::LIGHT_UV_FAILED, "light/uv failed"),
+    ];
+
+    /// The flags that mean a sensor has failed, as opposed to reporting interference.
+    const FAILURES: SensorFlags = SensorFlags(
+        SensorFlags::LIGHTNING_FAILED.0
+            | SensorFlags::PRESSURE_FAILED.0
+            | SensorFlags::TEMPERATURE_FAILED.0
+            | SensorFlags::HUMIDITY_FAILED.0
+            | SensorFlags::WIND_FAILED.0
+            | SensorFlags::PRECIP_FAILED.0
+            | SensorFlags::LIGHT_UV_FAILED.0,
+    );
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(&self, other: SensorFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether no flags at all are set.
+    pub fn is_ok(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether any sensor has failed. Lightning noise and disturbers don't count.
+    pub fn has_failures(&self) -> bool {
+        self.0 & SensorFlags::FAILURES.0 != 0
+    }
+
+    pub fn lightning_failed(&self) -> bool {
+        self.contains(SensorFlags::LIGHTNING_FAILED)
+    }
+
+    pub fn lightning_noise(&self) -> bool {
+        self.contains(SensorFlags::LIGHTNING_NOISE)
+    }
+
+    pub fn lightning_disturber(&self) -> bool {
+        self.contains(SensorFlags::LIGHTNING_DISTURBER)
+    }
+
+    pub fn pressure_failed(&self) -> bool {
+        self.contains(SensorFlags::PRESSURE_FAILED)
+    }
+
+    pub fn temperature_failed(&self) -> bool {
+        self.contains(SensorFlags::TEMPERATURE_FAILED)
+    }
+
+    pub fn humidity_failed(&self) -> bool {
+        self.contains(SensorFlags::HUMIDITY_FAILED)
+    }
+
+    pub fn wind_failed(&self) -> bool {
+        self.contains(SensorFlags::WIND_FAILED)
+    }
+
+    pub fn precip_failed(&self) -> bool {
+        self.contains(SensorFlags::PRECIP_FAILED)
+    }
+
+    pub fn light_uv_failed(&self) -> bool {
+        self.contains(SensorFlags::LIGHT_UV_FAILED)
+    }
+}
+
+impl Display for SensorFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_ok() {
+            return write!(f, "Sensors OK");
+        }
+
+        let mut names: Vec<String> = SensorFlags::NAMED
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| name.to_string())
+            .collect();
+        let unknown = SensorFlags::NAMED
+            .iter()
+            .fold(self.0, |bits, (flag, _)| bits & !flag.0);
+
+        if unknown != 0 {
+            names.push(format!("unknown bits {:#x}", unknown));
+        }
+
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+impl Serialize for SensorFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("SensorFlags", 11)?;
+        state.serialize_field("bits", &self.0)?;
+        state.serialize_field("ok", &self.is_ok())?;
+        state.serialize_field("lightning_failed", &self.lightning_failed())?;
+        state.serialize_field("lightning_noise", &self.lightning_noise())?;
+        state.serialize_field("lightning_disturber", &self.lightning_disturber())?;
+        state.serialize_field("pressure_failed", &self.pressure_failed())?;
+        state.serialize_field("temperature_failed", &self.temperature_failed())?;
+        state.serialize_field("humidity_failed", &self.humidity_failed())?;
+        state.serialize_field("wind_failed", &self.wind_failed())?;
+        state.serialize_field("precip_failed", &self.precip_failed())?;
+        state.serialize_field("light_uv_failed", &self.light_uv_failed())?;
+        state.end()
+    }
+}
+
+/// A health report from a device, from a `device_status` packet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeviceStatus {
+    pub time_epoch: u64,
+    pub serial_number: String,
+    pub hub_sn: String,
+    /// Seconds since the device last restarted.
+    pub uptime: u64,
+    /// Battery voltage, in Volts.
+    pub voltage: f32,
+    pub firmware_revision: u64,
+    /// Signal strength of the hub as seen by the device, in dBm.
+    pub rssi: i64,
+    /// Signal strength of the device as seen by the hub, in dBm.
+    pub hub_rssi: i64,
+    pub sensor_status: u64,
+    #[serde(deserialize_with = "bool_from_bool_or_int")]
+    pub debug: bool,
+}
+
+impl DeviceStatus {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.time_epoch as i64, 0)
+    }
+
+    pub fn get_sensor_flags(&self) -> SensorFlags {
+        SensorFlags::from_bits(self.sensor_status)
+    }
+}
+
+pub trait IntoDeviceStatus {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_device_status(&self) -> Option<DeviceStatus>;
+}
+
+/**
+A health report from a hub, from a `hub_status` packet. `radio_stats` is unpacked into its
+individual fields:
+
+0: Version
+1: Reboot Count
+2: I2C Bus Error Count
+3: Radio Status (0 = Radio Off, 1 = Radio On, 3 = Radio Active, 7 = BLE Connected)
+4: Radio Network ID
+*/
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HubStatus {
+    pub time_epoch: u64,
+    pub serial_number: String,
+    pub firmware_revision: String,
+    /// Seconds since the hub last restarted.
+    pub uptime: u64,
+    /// Wi-Fi signal strength, in dBm.
+    pub rssi: i64,
+    pub reset_flags: String,
+    pub seq: u64,
+    pub radio_version: u64,
+    pub reboot_count: u64,
+    pub bus_error_count: u64,
+    pub radio_status: u64,
+    pub radio_network_id: u64,
+}
+
+pub trait IntoHubStatus {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_hub_status(&self) -> Option<HubStatus>;
+}
+
+/// Common accessors for status reports, used to track uptime across a history of them.
+pub trait StatusReport {
+    fn serial_number(&self) -> &str;
+    fn time_epoch(&self) -> u64;
+    fn uptime(&self) -> u64;
+}
+
+impl StatusReport for DeviceStatus {
+    fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
+
+    fn time_epoch(&self) -> u64 {
+        self.time_epoch
+    }
+
+    fn uptime(&self) -> u64 {
+        self.uptime
+    }
+}
+
+impl StatusReport for HubStatus {
+    fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
+
+    fn time_epoch(&self) -> u64 {
+        self.time_epoch
+    }
+
+    fn uptime(&self) -> u64 {
+        self.uptime
+    }
+}
+
+/// A restart of a device or hub, detected by its uptime going backwards between two reports.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Reboot {
+    pub serial_number: String,
+    /// When the restart happened, estimated from the uptime in the first report after it.
+    pub time_epoch: u64,
+    /// The uptime reported just before the restart, in seconds.
+    pub previous_uptime: u64,
+}
+
+impl Reboot {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.time_epoch as i64, 0)
+    }
+}
+
+/// Finds every restart in a history of status reports, which must be ordered oldest first. Reports
+/// from different serial numbers may be interleaved.
+pub fn find_reboots<T: StatusReport>(reports: &[T]) -> Vec<Reboot> {
+    let mut last_uptimes: HashMap<&str, u64> = HashMap::new();
+    let mut reboots = Vec::new();
+
+    for report in reports {
+        let previous = last_uptimes.insert(report.serial_number(), report.uptime());
+
+        if let Some(previous_uptime) = previous {
+            if report.uptime() < previous_uptime {
+                reboots.push(Reboot {
+                    serial_number: report.serial_number().to_string(),
+                    time_epoch: report.time_epoch().saturating_sub(report.uptime()),
+                    previous_uptime,
+                });
+            }
+        }
+    }
+
+    reboots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Report(&'static str, u64, u64);
+
+    impl StatusReport for Report {
+        fn serial_number(&self) -> &str {
+            self.0
+        }
+
+        fn time_epoch(&self) -> u64 {
+            self.1
+        }
+
+        fn uptime(&self) -> u64 {
+            self.2
+        }
+    }
+
+    #[test]
+    fn finds_reboots_per_device() {
+        let reports = [
+            Report("ST-1", 1000, 500),
+            Report("HB-1", 1000, 9000),
+            Report("ST-1", 1060, 560),
+            Report("HB-1", 1010, 30),
+            Report("ST-1", 1120, 20),
+            Report("ST-1", 1180, 80),
+        ];
+
+        let reboots = find_reboots(&reports);
+
+        assert_eq!(reboots.len(), 2);
+        assert_eq!(reboots[0].serial_number, "HB-1");
+        assert_eq!(reboots[0].time_epoch, 980);
+        assert_eq!(reboots[0].previous_uptime, 9000);
+        assert_eq!(reboots[1].serial_number, "ST-1");
+        assert_eq!(reboots[1].time_epoch, 1100);
+        assert_eq!(reboots[1].previous_uptime, 560);
+    }
+
+    #[test]
+    fn decodes_sensor_flags() {
+        let flags = SensorFlags::from_bits(0b100001010);
+
+        assert!(flags.lightning_noise());
+        assert!(flags.pressure_failed());
+        assert!(flags.light_uv_failed());
+        assert!(!flags.lightning_failed());
+        assert!(!flags.is_ok());
+        assert!(flags.has_failures());
+        assert_eq!(
+            flags.to_string(),
+            "lightning noise, pressure failed, light/uv failed"
+        );
+    }
+
+    #[test]
+    fn does_not_count_interference_as_failures() {
+        let flags = SensorFlags::from_bits(0b110);
+
+        assert!(!flags.is_ok());
+        assert!(!flags.has_failures());
+        assert!(SensorFlags::default().is_ok());
+        assert_eq!(SensorFlags::default().to_string(), "Sensors OK");
+        assert_eq!(
+            SensorFlags::from_bits(1 << 12).to_string(),
+            "unknown bits 0x1000"
+        );
+        assert_eq!(
+            SensorFlags::from_bits(0x8000 | 0b1000).to_string(),
+            "pressure failed, unknown bits 0x8000"
+        );
+    }
+
+    #[test]
+    fn reads_debug_as_a_boolean_or_an_integer() {
+        let status = |debug: &str| {
+            serde_json::from_str::<DeviceStatus>(&format!(
+                r#"{{"time_epoch":1,"serial_number":"ST-1","hub_sn":"HB-1","uptime":2,
+                "voltage":2.6,"firmware_revision":17,"rssi":-60,"hub_rssi":-62,
+                "sensor_status":0,"debug":{}}}"#,
+                debug
+            ))
+        };
+
+        assert!(!status("0").unwrap().debug);
+        assert!(status("true").unwrap().debug);
+        assert!(status("2").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::units::{FormatWithUnits, Pressure, PressureUnit, Unit, UnitPreferences};
+
+/// The window barometric tendency is conventionally reported over.
+pub const DEFAULT_TREND_WINDOW: u64 = 3 * 3600;
+
+/// One station pressure reading, as selected by `QUERY_SELECT_PRESSURE_SAMPLES`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct PressureSample {
+    pub time_epoch: u64,
+    pub station_pressure: f32,
+}
+
+/**
+The characteristic of a pressure change, using the terms and thresholds the WMO and Met Office use
+for the change over three hours:
+
+- Steady: less than 0.1 mbar
+- Slowly: 0.1 to 1.5 mbar
+- (Rising or falling): 1.6 to 3.5 mbar
+- Quickly: 3.6 to 6.0 mbar
+- Very rapidly: more than 6.0 mbar
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureTendency {
+    RisingVeryRapidly,
+    RisingQuickly,
+    Rising,
+    RisingSlowly,
+    Steady,
+    FallingSlowly,
+    Falling,
+    FallingQuickly,
+    FallingVeryRapidly,
+}
+
+impl PressureTendency {
+    /// Classifies a change in mbar over three hours.
+    pub fn from_change(change_per_3h: f32) -> Self {
+        let rising = change_per_3h > 0.0;
+
+        match change_per_3h.abs() {
+            change if change < 0.1 => PressureTendency::Steady,
+            change if change < 1.6 && rising => PressureTendency::RisingSlowly,
+            change if change < 1.6 => PressureTendency::FallingSlowly,
+            change if change < 3.6 && rising => PressureTendency::Rising,
+            change if change < 3.6 => PressureTendency::Falling,
+            change if change <= 6.0 && rising => PressureTendency::RisingQuickly,
+            change if change <= 6.0 => PressureTendency::FallingQuickly,
+            _ if rising => PressureTendency::RisingVeryRapidly,
+            _ => PressureTendency::FallingVeryRapidly,
+        }
+    }
+}
+
+impl Display for PressureTendency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            PressureTendency::RisingVeryRapidly => "Rising very rapidly",
+            PressureTendency::RisingQuickly => "Rising quickly",
+            PressureTendency::Rising => "Rising",
+            PressureTendency::RisingSlowly => "Rising slowly",
+            PressureTendency::Steady => "Steady",
+            PressureTendency::FallingSlowly => "Falling slowly",
+            PressureTendency::Falling => "Falling",
+            PressureTendency::FallingQuickly => "Falling quickly",
+            PressureTendency::FallingVeryRapidly => "Falling very rapidly",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+/// How station pressure has changed over a window of time.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PressureTrend {
+    /// The time of the first and last samples the trend is based on.
+    pub start_epoch: u64,
+    pub end_epoch: u64,
+    pub sample_count: usize,
+    /// The change in mbar over the samples' span, from a least-squares fit so that a single
+    /// noisy reading at either end doesn't skew it.
+    pub change: f32,
+    /// The same rate of change scaled to three hours, which is what the tendency is based on.
+    pub change_per_3h: f32,
+    pub tendency: PressureTendency,
+}
+
+impl PressureTrend {
+    /**
+    Computes the trend from samples covering a window of `window` seconds. There's no trend if the
+    samples span less than half the window, since extrapolating a few minutes of readings to three
+    hours would exaggerate sensor noise.
+    */
+    pub fn from_samples(samples: &[PressureSample], window: u64) -> Option<PressureTrend> {
+        let start_epoch = samples.iter().map(|sample| sample.time_epoch).min()?;
+        let end_epoch = samples.iter().map(|sample| sample.time_epoch).max()?;
+        let span = end_epoch - start_epoch;
+
+        if span == 0 || span * 2 < window {
+            return None;
+        }
+
+        // Times are taken relative to the first sample so that squaring them doesn't lose precision.
+        let n = samples.len() as f64;
+        let times = samples
+            .iter()
+            .map(|sample| (sample.time_epoch - start_epoch) as f64);
+        let mean_time = times.clone().sum::<f64>() / n;
+        let mean_pressure = samples
+            .iter()
+            .map(|sample| sample.station_pressure as f64)
+            .sum::<f64>()
+            / n;
+
+        let (covariance, variance) =
+            times
+                .zip(samples)
+                .fold((0.0, 0.0), |(covariance, variance), (time, sample)| {
+                    let dt = time - mean_time;
+                    (
+                        covariance + dt * (sample.station_pressure as f64 - mean_pressure),
+                        variance + dt * dt,
+                    )
+                });
+        let slope = covariance / variance;
+        let change_per_3h = (slope * 3.0 * 3600.0) as f32;
+
+        Some(PressureTrend {
+            start_epoch,
+            end_epoch,
+            sample_count: samples.len(),
+            change: (slope * span as f64) as f32,
+            change_per_3h,
+            tendency: PressureTendency::from_change(change_per_3h),
+        })
+    }
+}
+
+impl PressureTrend {
+    /// The same trend with changes in the given units.
+    pub fn in_units(&self, units: &UnitPreferences) -> Self {
+        let pressure = |value| units.pressure(Pressure::new(value, PressureUnit::Millibars));
+
+        Self {
+            change: pressure(self.change).value(),
+            change_per_3h: pressure(self.change_per_3h).value(),
+            ..*self
+        }
+    }
+}
+
+impl FormatWithUnits for PressureTrend {
+    fn fmt_with_units(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        units: &UnitPreferences,
+    ) -> std::fmt::Result {
+        let change = units.pressure(Pressure::new(self.change_per_3h, PressureUnit::Millibars));
+
+        write!(
+            f,
+            "{} ({:+.*} {}/3h)",
+            self.tendency,
+            change.unit().default_precision(),
+            change.value(),
+            change.unit()
+        )
+    }
+}
+
+impl Display for PressureTrend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_units(f, &UnitPreferences::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One sample every five minutes over `minutes`, changing linearly by `change_per_hour`.
+    fn linear_samples(minutes: u64, change_per_hour: f32) -> Vec<PressureSample> {
+        (0..=minutes / 5)
+            .map(|step| PressureSample {
+                time_epoch: 1_700_000_000 + step * 300,
+                station_pressure: 1000.0 + change_per_hour * step as f32 / 12.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classifies_changes_over_three_hours() {
+        assert_eq!(
+            PressureTendency::from_change(0.05),
+            PressureTendency::Steady
+        );
+        assert_eq!(
+            PressureTendency::from_change(-0.05),
+            PressureTendency::Steady
+        );
+        assert_eq!(
+            PressureTendency::from_change(1.0),
+            PressureTendency::RisingSlowly
+        );
+        assert_eq!(
+            PressureTendency::from_change(-2.0),
+            PressureTendency::Falling
+        );
+        assert_eq!(
+            PressureTendency::from_change(6.0),
+            PressureTendency::RisingQuickly
+        );
+        assert_eq!(
+            PressureTendency::from_change(-6.1),
+            PressureTendency::FallingVeryRapidly
+        );
+    }
+
+    #[test]
+    fn fits_a_linear_trend() {
+        let trend = PressureTrend::from_samples(&linear_samples(180, -1.0), 3 * 3600).unwrap();
+
+        assert_eq!(trend.sample_count, 37);
+        assert_eq!(trend.end_epoch - trend.start_epoch, 3 * 3600);
+        assert!((trend.change - -3.0).abs() < 0.01);
+        assert!((trend.change_per_3h - -3.0).abs() < 0.01);
+        assert_eq!(trend.tendency, PressureTendency::Falling);
+    }
+
+    #[test]
+    fn scales_shorter_spans_to_three_hours() {
+        let trend = PressureTrend::from_samples(&linear_samples(120, 0.5), 3 * 3600).unwrap();
+
+        assert!((trend.change - 1.0).abs() < 0.01);
+        assert!((trend.change_per_3h - 1.5).abs() < 0.01);
+        assert_eq!(trend.tendency, PressureTendency::RisingSlowly);
+    }
+
+    #[test]
+    fn needs_samples_covering_half_the_window() {
+        assert!(PressureTrend::from_samples(&[], 3 * 3600).is_none());
+        assert!(PressureTrend::from_samples(&linear_samples(0, 1.0), 3 * 3600).is_none());
+        assert!(PressureTrend::from_samples(&linear_samples(85, 1.0), 3 * 3600).is_none());
+        assert!(PressureTrend::from_samples(&linear_samples(90, 1.0), 3 * 3600).is_some());
+    }
+}
@@ -1,9 +1,82 @@
-use std::fmt::Display;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug)]
+/// A unit of measurement for one kind of quantity. Every unit converts through a base unit for
+/// its kind, so any unit can be converted to any other of the same kind.
+pub trait Unit: Copy + Display {
+    /// Converts a value in this unit to the base unit.
+    fn to_base(self, value: f32) -> f32;
+    /// Converts a value in the base unit to this unit.
+    fn to_unit(self, value: f32) -> f32;
+    /// How many decimal places values in this unit are shown with, unless the format asks for a
+    /// precision with `{:.N}`.
+    fn default_precision(self) -> usize;
+}
+
+/// A value in some unit, which can be converted to any other unit of the same kind.
+#[derive(Debug, Clone, Copy)]
+pub struct Quantity<U: Unit> {
+    value: f32,
+    unit: U,
+}
+
+impl<U: Unit> Quantity<U> {
+    pub fn new(value: f32, unit: U) -> Self {
+        Self { value, unit }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn unit(&self) -> U {
+        self.unit
+    }
+
+    /// The same quantity in another unit.
+    pub fn to(self, unit: U) -> Self {
+        Self::new(unit.to_unit(self.unit.to_base(self.value)), unit)
+    }
+}
+
+impl<U: Unit> Display for Quantity<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let precision = f
+            .precision()
+            .unwrap_or_else(|| self.unit.default_precision());
+
+        write!(f, "{:.*} {}", precision, self.value, self.unit)
+    }
+}
+
+/// Temperature units, based on Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TempUnit {
     F,
     C,
+    K,
+}
+
+impl Unit for TempUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            TempUnit::C => value,
+            TempUnit::F => (value - 32.0) * (5.0 / 9.0),
+            TempUnit::K => value - 273.15,
+        }
+    }
+
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            TempUnit::C => value,
+            TempUnit::F => value * (9.0 / 5.0) + 32.0,
+            TempUnit::K => value + 273.15,
+        }
+    }
+
+    fn default_precision(self) -> usize {
+        1
+    }
 }
 
 impl Display for TempUnit {
@@ -11,95 +84,664 @@ impl Display for TempUnit {
         match self {
             TempUnit::C => write!(f, "°C"),
             TempUnit::F => write!(f, "°F"),
+            TempUnit::K => write!(f, "K"),
         }
     }
 }
 
-pub struct Temperature {
-    value: f32,
-    unit: TempUnit,
+impl FromStr for TempUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "f" | "°f" | "fahrenheit" => Ok(TempUnit::F),
+            "c" | "°c" | "celsius" => Ok(TempUnit::C),
+            "k" | "kelvin" => Ok(TempUnit::K),
+            other => Err(format!("unknown temperature unit: {}", other)),
+        }
+    }
 }
 
+pub type Temperature = Quantity<TempUnit>;
+
 impl Temperature {
-    pub fn new(temp: f32, unit: TempUnit) -> Self {
-        Self { value: temp, unit }
+    pub fn into_f(self) -> Temperature {
+        self.to(TempUnit::F)
     }
 
-    pub fn into_f(&self) -> Temperature {
-        Temperature::new(
-            match self.unit {
-                TempUnit::C => self.value * (9.0 / 5.0) + 32.0,
-                TempUnit::F => self.value,
-            },
-            TempUnit::F,
-        )
+    pub fn into_c(self) -> Temperature {
+        self.to(TempUnit::C)
     }
 
-    pub fn into_c(&self) -> Temperature {
-        Temperature::new(
-            match self.unit {
-                TempUnit::C => self.value,
-                TempUnit::F => (self.value - 32.0) * (5.0 / 9.0),
-            },
-            TempUnit::C,
-        )
+    pub fn into_k(self) -> Temperature {
+        self.to(TempUnit::K)
     }
 }
 
-impl Display for Temperature {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.value, self.unit)
-    }
-}
+/**
+Speed units, based on meters per second.
 
-#[derive(Debug)]
+Beaufort numbers use the WMO's empirical relation v = 0.836 B^(3/2) m/s, and aren't rounded, so
+"3.6 Bft" is between force 3 and force 4. They're shown as whole numbers by default.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SpeedUnit {
     MetersPerSecond,
+    KilometersPerHour,
     MilesPerHour,
+    Knots,
+    Beaufort,
+}
+
+impl Unit for SpeedUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            SpeedUnit::MetersPerSecond => value,
+            SpeedUnit::KilometersPerHour => value / 3.6,
+            SpeedUnit::MilesPerHour => value * 0.44704, // 1 mph = 0.44704 m/s
+            SpeedUnit::Knots => value * (1852.0 / 3600.0),
+            SpeedUnit::Beaufort => 0.836 * value.max(0.0).powf(1.5),
+        }
+    }
+
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            SpeedUnit::MetersPerSecond => value,
+            SpeedUnit::KilometersPerHour => value * 3.6,
+            SpeedUnit::MilesPerHour => value / 0.44704, // 1 m/s = 2.23694 mph
+            SpeedUnit::Knots => value * (3600.0 / 1852.0),
+            SpeedUnit::Beaufort => (value.max(0.0) / 0.836).powf(2.0 / 3.0),
+        }
+    }
+
+    fn default_precision(self) -> usize {
+        match self {
+            SpeedUnit::Beaufort => 0,
+            _ => 1,
+        }
+    }
 }
 
 impl Display for SpeedUnit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             SpeedUnit::MetersPerSecond => write!(f, "m/s"),
+            SpeedUnit::KilometersPerHour => write!(f, "km/h"),
             SpeedUnit::MilesPerHour => write!(f, "mph"),
+            SpeedUnit::Knots => write!(f, "kn"),
+            SpeedUnit::Beaufort => write!(f, "Bft"),
         }
     }
 }
 
-pub struct Speed {
-    value: f32,
-    unit: SpeedUnit,
+impl FromStr for SpeedUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "m/s" | "mps" => Ok(SpeedUnit::MetersPerSecond),
+            "km/h" | "kmh" | "kph" => Ok(SpeedUnit::KilometersPerHour),
+            "mph" => Ok(SpeedUnit::MilesPerHour),
+            "kn" | "kt" | "knots" => Ok(SpeedUnit::Knots),
+            "bft" | "beaufort" => Ok(SpeedUnit::Beaufort),
+            other => Err(format!("unknown speed unit: {}", other)),
+        }
+    }
 }
 
+pub type Speed = Quantity<SpeedUnit>;
+
 impl Speed {
-    pub fn new(value: f32, unit: SpeedUnit) -> Self {
-        Self { value, unit }
+    pub fn into_meters_per_second(self) -> Speed {
+        self.to(SpeedUnit::MetersPerSecond)
+    }
+
+    pub fn into_miles_per_hour(self) -> Speed {
+        self.to(SpeedUnit::MilesPerHour)
+    }
+}
+
+/// Pressure units, based on millibars. Hectopascals are the same size as millibars, but are
+/// labeled as such for those who expect SI units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressureUnit {
+    Millibars,
+    Hectopascals,
+    Kilopascals,
+    InchesOfMercury,
+    MillimetersOfMercury,
+}
+
+impl Unit for PressureUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            PressureUnit::Millibars | PressureUnit::Hectopascals => value,
+            PressureUnit::Kilopascals => value * 10.0,
+            PressureUnit::InchesOfMercury => value * 33.863_89,
+            PressureUnit::MillimetersOfMercury => value * 1.333_224,
+        }
+    }
+
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            PressureUnit::Millibars | PressureUnit::Hectopascals => value,
+            PressureUnit::Kilopascals => value / 10.0,
+            PressureUnit::InchesOfMercury => value / 33.863_89,
+            PressureUnit::MillimetersOfMercury => value / 1.333_224,
+        }
+    }
+
+    fn default_precision(self) -> usize {
+        match self {
+            PressureUnit::Kilopascals | PressureUnit::InchesOfMercury => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Display for PressureUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PressureUnit::Millibars => write!(f, "mbar"),
+            PressureUnit::Hectopascals => write!(f, "hPa"),
+            PressureUnit::Kilopascals => write!(f, "kPa"),
+            PressureUnit::InchesOfMercury => write!(f, "inHg"),
+            PressureUnit::MillimetersOfMercury => write!(f, "mmHg"),
+        }
+    }
+}
+
+impl FromStr for PressureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mbar" | "mb" => Ok(PressureUnit::Millibars),
+            "hpa" => Ok(PressureUnit::Hectopascals),
+            "kpa" => Ok(PressureUnit::Kilopascals),
+            "inhg" => Ok(PressureUnit::InchesOfMercury),
+            "mmhg" => Ok(PressureUnit::MillimetersOfMercury),
+            other => Err(format!("unknown pressure unit: {}", other)),
+        }
+    }
+}
+
+pub type Pressure = Quantity<PressureUnit>;
+
+/// Precipitation depth units, based on millimeters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrecipitationUnit {
+    Millimeters,
+    Inches,
+}
+
+impl Unit for PrecipitationUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            PrecipitationUnit::Millimeters => value,
+            PrecipitationUnit::Inches => value * 25.4,
+        }
     }
 
-    pub fn into_meters_per_second(&self) -> Speed {
-        Speed::new(
-            match self.unit {
-                SpeedUnit::MetersPerSecond => self.value,
-                SpeedUnit::MilesPerHour => self.value * 0.44704, // 1 mph = 0.44704 m/s
-            },
-            SpeedUnit::MetersPerSecond,
-        )
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            PrecipitationUnit::Millimeters => value,
+            PrecipitationUnit::Inches => value / 25.4,
+        }
     }
 
-    pub fn into_miles_per_hour(&self) -> Speed {
-        Speed::new(
-            match self.unit {
-                SpeedUnit::MetersPerSecond => self.value / 0.44704, // 1 m/s = 2.23694 mph
-                SpeedUnit::MilesPerHour => self.value,
-            },
-            SpeedUnit::MilesPerHour,
-        )
+    fn default_precision(self) -> usize {
+        match self {
+            PrecipitationUnit::Millimeters => 1,
+            PrecipitationUnit::Inches => 2,
+        }
     }
 }
 
-impl Display for Speed {
+impl Display for PrecipitationUnit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.value, self.unit)
+        match self {
+            PrecipitationUnit::Millimeters => write!(f, "mm"),
+            PrecipitationUnit::Inches => write!(f, "in"),
+        }
+    }
+}
+
+impl FromStr for PrecipitationUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mm" => Ok(PrecipitationUnit::Millimeters),
+            "in" => Ok(PrecipitationUnit::Inches),
+            other => Err(format!("unknown precipitation unit: {}", other)),
+        }
+    }
+}
+
+pub type Precipitation = Quantity<PrecipitationUnit>;
+
+/// Distance units, based on kilometers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceUnit {
+    Kilometers,
+    Miles,
+}
+
+impl Unit for DistanceUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            DistanceUnit::Kilometers => value,
+            DistanceUnit::Miles => value * 1.609_344,
+        }
+    }
+
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            DistanceUnit::Kilometers => value,
+            DistanceUnit::Miles => value / 1.609_344,
+        }
+    }
+
+    fn default_precision(self) -> usize {
+        0
+    }
+}
+
+impl Display for DistanceUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistanceUnit::Kilometers => write!(f, "km"),
+            DistanceUnit::Miles => write!(f, "mi"),
+        }
+    }
+}
+
+impl FromStr for DistanceUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "km" => Ok(DistanceUnit::Kilometers),
+            "mi" => Ok(DistanceUnit::Miles),
+            other => Err(format!("unknown distance unit: {}", other)),
+        }
+    }
+}
+
+pub type Distance = Quantity<DistanceUnit>;
+
+/// Illuminance units, based on lux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IlluminanceUnit {
+    Lux,
+    FootCandles,
+}
+
+impl Unit for IlluminanceUnit {
+    fn to_base(self, value: f32) -> f32 {
+        match self {
+            IlluminanceUnit::Lux => value,
+            IlluminanceUnit::FootCandles => value * 10.763_91, // 1 fc = 1 lm/ft^2
+        }
+    }
+
+    fn to_unit(self, value: f32) -> f32 {
+        match self {
+            IlluminanceUnit::Lux => value,
+            IlluminanceUnit::FootCandles => value / 10.763_91,
+        }
+    }
+
+    fn default_precision(self) -> usize {
+        0
+    }
+}
+
+impl Display for IlluminanceUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IlluminanceUnit::Lux => write!(f, "lx"),
+            IlluminanceUnit::FootCandles => write!(f, "fc"),
+        }
+    }
+}
+
+impl FromStr for IlluminanceUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "lx" | "lux" => Ok(IlluminanceUnit::Lux),
+            "fc" | "foot-candles" | "footcandles" => Ok(IlluminanceUnit::FootCandles),
+            other => Err(format!("unknown illuminance unit: {}", other)),
+        }
+    }
+}
+
+pub type Illuminance = Quantity<IlluminanceUnit>;
+
+/**
+The units to show each kind of quantity in. Parsed from "metric", "imperial", or comma-separated
+kind=unit pairs, optionally starting with one of those as the base, e.g.
+"metric,pressure=inhg" or "temperature=c,speed=kn". Kinds left out keep their defaults.
+
+The default is what output looked like before preferences existed: Fahrenheit and mph, with
+metric pressure, rain, distance and illuminance.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnitPreferences {
+    pub temperature: TempUnit,
+    pub speed: SpeedUnit,
+    pub pressure: PressureUnit,
+    pub precipitation: PrecipitationUnit,
+    pub distance: DistanceUnit,
+    pub illuminance: IlluminanceUnit,
+}
+
+impl UnitPreferences {
+    pub const METRIC: UnitPreferences = UnitPreferences {
+        temperature: TempUnit::C,
+        speed: SpeedUnit::KilometersPerHour,
+        pressure: PressureUnit::Hectopascals,
+        precipitation: PrecipitationUnit::Millimeters,
+        distance: DistanceUnit::Kilometers,
+        illuminance: IlluminanceUnit::Lux,
+    };
+
+    pub const IMPERIAL: UnitPreferences = UnitPreferences {
+        temperature: TempUnit::F,
+        speed: SpeedUnit::MilesPerHour,
+        pressure: PressureUnit::InchesOfMercury,
+        precipitation: PrecipitationUnit::Inches,
+        distance: DistanceUnit::Miles,
+        illuminance: IlluminanceUnit::FootCandles,
+    };
+
+    pub fn temperature(&self, temperature: Temperature) -> Temperature {
+        temperature.to(self.temperature)
+    }
+
+    pub fn speed(&self, speed: Speed) -> Speed {
+        speed.to(self.speed)
+    }
+
+    pub fn pressure(&self, pressure: Pressure) -> Pressure {
+        pressure.to(self.pressure)
+    }
+
+    pub fn precipitation(&self, precipitation: Precipitation) -> Precipitation {
+        precipitation.to(self.precipitation)
+    }
+
+    pub fn distance(&self, distance: Distance) -> Distance {
+        distance.to(self.distance)
+    }
+
+    pub fn illuminance(&self, illuminance: Illuminance) -> Illuminance {
+        illuminance.to(self.illuminance)
+    }
+}
+
+impl Default for UnitPreferences {
+    fn default() -> Self {
+        Self {
+            temperature: TempUnit::F,
+            speed: SpeedUnit::MilesPerHour,
+            pressure: PressureUnit::Millibars,
+            precipitation: PrecipitationUnit::Millimeters,
+            distance: DistanceUnit::Kilometers,
+            illuminance: IlluminanceUnit::Lux,
+        }
+    }
+}
+
+/// Parses a comma-separated list of kind=unit pairs, along with at most one "metric" or "imperial"
+/// preset anywhere in it, which the pairs then override. Case is ignored.
+impl FromStr for UnitPreferences {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let (presets, pairs): (Vec<&str>, Vec<&str>) = s
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .partition(|pair| !pair.contains('='));
+
+        if let Some(preset) = presets
+            .iter()
+            .find(|preset| !matches!(**preset, "metric" | "imperial"))
+        {
+            return Err(format!(
+                "expected metric, imperial or kind=unit, got {}",
+                preset
+            ));
+        }
+
+        let mut preferences = match presets[..] {
+            [] => UnitPreferences::default(),
+            ["metric"] => UnitPreferences::METRIC,
+            ["imperial"] => UnitPreferences::IMPERIAL,
+            _ => {
+                return Err(format!(
+                    "expected one preset, got {}",
+                    presets.join(" and ")
+                ))
+            }
+        };
+
+        for pair in pairs {
+            let (kind, unit) = pair.split_once('=').unwrap();
+
+            match kind.trim() {
+                "temperature" | "temp" => preferences.temperature = unit.parse()?,
+                "speed" | "wind" => preferences.speed = unit.parse()?,
+                "pressure" => preferences.pressure = unit.parse()?,
+                "precipitation" | "rain" => preferences.precipitation = unit.parse()?,
+                "distance" => preferences.distance = unit.parse()?,
+                "illuminance" | "light" => preferences.illuminance = unit.parse()?,
+                other => return Err(format!("unknown kind of quantity: {}", other)),
+            }
+        }
+
+        Ok(preferences)
+    }
+}
+
+/// Serializes as the symbol of each unit, to label values converted to these units in JSON.
+impl Serialize for UnitPreferences {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("UnitPreferences", 6)?;
+        state.serialize_field("temperature", &self.temperature.to_string())?;
+        state.serialize_field("speed", &self.speed.to_string())?;
+        state.serialize_field("pressure", &self.pressure.to_string())?;
+        state.serialize_field("precipitation", &self.precipitation.to_string())?;
+        state.serialize_field("distance", &self.distance.to_string())?;
+        state.serialize_field("illuminance", &self.illuminance.to_string())?;
+        state.end()
+    }
+}
+
+/// Something whose text representation depends on unit preferences. Its `Display` uses the
+/// default preferences, and `with_units` displays it with others.
+pub trait FormatWithUnits {
+    fn fmt_with_units(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        units: &UnitPreferences,
+    ) -> std::fmt::Result;
+
+    fn with_units<'a>(&'a self, units: &'a UnitPreferences) -> WithUnits<'a, Self> {
+        WithUnits { value: self, units }
+    }
+}
+
+/// Displays a value with the given unit preferences.
+pub struct WithUnits<'a, T: ?Sized> {
+    value: &'a T,
+    units: &'a UnitPreferences,
+}
+
+impl<T: FormatWithUnits + ?Sized> Display for WithUnits<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.value.fmt_with_units(f, self.units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close<U: Unit>(quantity: Quantity<U>, expected: f32) {
+        assert!(
+            (quantity.value() - expected).abs() < 0.01,
+            "expected {}, got {}",
+            expected,
+            quantity
+        );
+    }
+
+    #[test]
+    fn converts_between_units() {
+        assert_close(Temperature::new(100.0, TempUnit::C).into_f(), 212.0);
+        assert_close(Temperature::new(-40.0, TempUnit::F).into_c(), -40.0);
+        assert_close(Temperature::new(0.0, TempUnit::C).into_k(), 273.15);
+        assert_close(
+            Speed::new(10.0, SpeedUnit::MetersPerSecond).to(SpeedUnit::KilometersPerHour),
+            36.0,
+        );
+        assert_close(
+            Speed::new(10.0, SpeedUnit::Knots).to(SpeedUnit::MilesPerHour),
+            11.51,
+        );
+        assert_close(
+            Pressure::new(1013.25, PressureUnit::Millibars).to(PressureUnit::InchesOfMercury),
+            29.92,
+        );
+        assert_close(
+            Pressure::new(760.0, PressureUnit::MillimetersOfMercury).to(PressureUnit::Kilopascals),
+            101.32,
+        );
+        assert_close(
+            Precipitation::new(1.0, PrecipitationUnit::Inches).to(PrecipitationUnit::Millimeters),
+            25.4,
+        );
+        assert_close(
+            Distance::new(10.0, DistanceUnit::Miles).to(DistanceUnit::Kilometers),
+            16.09,
+        );
+        assert_close(
+            Illuminance::new(1076.391, IlluminanceUnit::Lux).to(IlluminanceUnit::FootCandles),
+            100.0,
+        );
+    }
+
+    #[test]
+    fn converts_beaufort_numbers() {
+        assert_close(
+            Speed::new(4.0, SpeedUnit::Beaufort).into_meters_per_second(),
+            6.69,
+        );
+        assert_close(
+            Speed::new(6.69, SpeedUnit::MetersPerSecond).to(SpeedUnit::Beaufort),
+            4.0,
+        );
+        assert_close(
+            Speed::new(-1.0, SpeedUnit::MetersPerSecond).to(SpeedUnit::Beaufort),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn formats_with_the_units_precision() {
+        assert_eq!(Temperature::new(21.56, TempUnit::C).to_string(), "21.6 °C");
+        assert_eq!(
+            Pressure::new(29.921, PressureUnit::InchesOfMercury).to_string(),
+            "29.92 inHg"
+        );
+        assert_eq!(
+            Illuminance::new(20000.4, IlluminanceUnit::Lux).to_string(),
+            "20000 lx"
+        );
+        assert_eq!(
+            format!("{:.2}", Distance::new(3.0, DistanceUnit::Kilometers)),
+            "3.00 km"
+        );
+    }
+
+    #[test]
+    fn parses_unit_names() {
+        assert_eq!("Fahrenheit".parse(), Ok(TempUnit::F));
+        assert_eq!("kph".parse(), Ok(SpeedUnit::KilometersPerHour));
+        assert_eq!("hPa".parse(), Ok(PressureUnit::Hectopascals));
+        assert_eq!("lux".parse(), Ok(IlluminanceUnit::Lux));
+        assert_eq!("fc".parse(), Ok(IlluminanceUnit::FootCandles));
+        assert!("furlongs".parse::<DistanceUnit>().is_err());
+    }
+
+    #[test]
+    fn parses_unit_preferences() {
+        assert_eq!("".parse(), Ok(UnitPreferences::default()));
+        assert_eq!("metric".parse(), Ok(UnitPreferences::METRIC));
+        assert_eq!(
+            "imperial, pressure=mbar".parse(),
+            Ok(UnitPreferences {
+                pressure: PressureUnit::Millibars,
+                ..UnitPreferences::IMPERIAL
+            })
+        );
+        assert_eq!(
+            "temp=c,wind=kn,light=fc".parse(),
+            Ok(UnitPreferences {
+                temperature: TempUnit::C,
+                speed: SpeedUnit::Knots,
+                illuminance: IlluminanceUnit::FootCandles,
+                ..UnitPreferences::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parses_presets_anywhere_in_any_case() {
+        let metric_in_inhg = UnitPreferences {
+            pressure: PressureUnit::InchesOfMercury,
+            ..UnitPreferences::METRIC
+        };
+
+        assert_eq!("Metric".parse(), Ok(UnitPreferences::METRIC));
+        assert_eq!(" IMPERIAL ".parse(), Ok(UnitPreferences::IMPERIAL));
+        assert_eq!("pressure=inhg,metric".parse(), Ok(metric_in_inhg));
+        assert_eq!("Pressure=inHg, METRIC".parse(), Ok(metric_in_inhg));
+        assert_eq!(
+            "mph,metric".parse::<UnitPreferences>(),
+            Err("expected metric, imperial or kind=unit, got mph".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_unit_preferences() {
+        assert!("metric,imperial".parse::<UnitPreferences>().is_err());
+        assert!("temperature=rankine".parse::<UnitPreferences>().is_err());
+        assert!("humidity=%".parse::<UnitPreferences>().is_err());
+        assert!("si".parse::<UnitPreferences>().is_err());
+    }
+
+    #[test]
+    fn serializes_unit_preferences_as_symbols() {
+        assert_eq!(
+            serde_json::to_value(UnitPreferences::IMPERIAL).unwrap(),
+            serde_json::json!({
+                "temperature": "°F",
+                "speed": "mph",
+                "pressure": "inHg",
+                "precipitation": "in",
+                "distance": "mi",
+                "illuminance": "fc",
+            })
+        );
     }
 }
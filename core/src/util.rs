@@ -1,6 +1,6 @@
-use chrono::Duration;
-use core::fmt::Display;
+use chrono::{Duration, Utc};
 use num_traits::int::PrimInt;
+use std::fmt::Display;
 
 pub trait Counted {
     fn counted(&self, singular: &str) -> String;
@@ -35,3 +35,73 @@ pub fn format_duration(duration: Duration) -> String {
 
     pieces.join(", ")
 }
+
+/// The current time as a Unix epoch, in seconds.
+pub fn now_epoch() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use chrono::{FixedOffset, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone};
+
+    /**
+    A time zone like Chile's, where DST starts at midnight: clocks go from 23:59:59 on
+    September 7, 2024 straight to 01:00 on September 8, at 04:00 UTC, so that day has no midnight.
+    */
+    #[derive(Debug, Clone, Copy)]
+    pub struct MidnightDst;
+
+    /// 00:00 local time on September 8, 2024 in `MidnightDst`, which never happens.
+    pub fn skipped_midnight() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 9, 8)
+            .unwrap()
+            .and_time(NaiveTime::MIN)
+    }
+
+    impl MidnightDst {
+        fn standard() -> FixedOffset {
+            FixedOffset::west_opt(4 * 3600).unwrap()
+        }
+
+        fn daylight() -> FixedOffset {
+            FixedOffset::west_opt(3 * 3600).unwrap()
+        }
+    }
+
+    impl TimeZone for MidnightDst {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            MidnightDst
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let transition = skipped_midnight();
+
+            if *local < transition {
+                LocalResult::Single(Self::standard())
+            } else if *local < transition + chrono::Duration::hours(1) {
+                LocalResult::None
+            } else {
+                LocalResult::Single(Self::daylight())
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            if *utc < skipped_midnight() - Self::standard().fix() {
+                Self::standard()
+            } else {
+                Self::daylight()
+            }
+        }
+    }
+}
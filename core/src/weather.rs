@@ -1,11 +1,22 @@
 use chrono::{DateTime, Local, LocalResult, TimeZone};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::fmt::Display;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 use wasm_bindgen::JsValue;
 
 use crate::{
-    units::{Speed, SpeedUnit, TempUnit, Temperature},
+    derived::{self, DerivedMetrics},
+    station::{self, StationConfig},
+    trend::PressureTrend,
+    units::{
+        Distance, DistanceUnit, FormatWithUnits, Illuminance, IlluminanceUnit, Precipitation,
+        PrecipitationUnit, Pressure, PressureUnit, Speed, SpeedUnit, TempUnit, Temperature,
+        UnitPreferences,
+    },
     util::format_duration,
+    wind::{Beaufort, WindDirection, WindSummary},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -27,9 +38,9 @@ impl From<f64> for PrecipitationType {
     }
 }
 
-impl Into<JsValue> for PrecipitationType {
-    fn into(self) -> JsValue {
-        match self {
+impl From<PrecipitationType> for JsValue {
+    fn from(item: PrecipitationType) -> JsValue {
+        match item {
             PrecipitationType::None => JsValue::from(0),
             PrecipitationType::Rain => JsValue::from(1),
             PrecipitationType::Hail => JsValue::from(2),
@@ -91,8 +102,11 @@ impl<'de> Deserialize<'de> for PrecipitationType {
 15: Lightning Strike Count, count
 16: Battery, Volts
 17: Report Interval, Minutes
+
+Each record also carries the serial numbers of the device that took it and the hub that relayed it.
+Records merged from a legacy AIR and SKY pair use both device serial numbers, joined by a slash.
 */
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Weather {
     pub time_epoch: u64,
     pub wind_lull: f32,
@@ -108,10 +122,24 @@ pub struct Weather {
     pub solar_radiation: u32,
     pub rain_over_prev_minute: f32,
     pub precip_type: PrecipitationType,
-    pub lightning_avg_distance: u32,
+    /// Null in observations merged from an AIR that detected no lightning.
+    pub lightning_avg_distance: Option<u32>,
     pub lightning_strike_count: u32,
     pub battery_voltage: f32,
     pub report_interval: u16,
+    #[serde(default, deserialize_with = "string_or_null")]
+    pub serial_number: String,
+    #[serde(default, deserialize_with = "string_or_null")]
+    pub hub_sn: String,
+}
+
+/// Reads a string that may be null, as it is in rows stored before serial numbers were recorded,
+/// as an empty string.
+fn string_or_null<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl Weather {
@@ -134,56 +162,713 @@ impl Weather {
     pub fn get_wind_gust(&self) -> Speed {
         Speed::new(self.wind_gust, SpeedUnit::MetersPerSecond)
     }
+
+    /// The direction the wind blew from over the report interval, or calm or variable.
+    pub fn get_wind_direction(&self) -> WindDirection {
+        WindDirection::from_interval(self.wind_avg, self.wind_lull, self.wind_direction)
+    }
+
+    /// The Beaufort force of the average wind.
+    pub fn get_beaufort(&self) -> Beaufort {
+        Beaufort::from_speed(self.wind_avg)
+    }
+
+    pub fn get_station_pressure(&self) -> Pressure {
+        Pressure::new(self.station_pressure, PressureUnit::Millibars)
+    }
+
+    pub fn get_rain_over_prev_minute(&self) -> Precipitation {
+        Precipitation::new(self.rain_over_prev_minute, PrecipitationUnit::Millimeters)
+    }
+
+    pub fn get_lightning_avg_distance(&self) -> Option<Distance> {
+        self.lightning_avg_distance
+            .map(|distance| Distance::new(distance as f32, DistanceUnit::Kilometers))
+    }
+
+    pub fn get_illuminance(&self) -> Illuminance {
+        Illuminance::new(self.illuminance as f32, IlluminanceUnit::Lux)
+    }
+
+    pub fn get_dew_point(&self) -> Temperature {
+        derived::dew_point(self.air_temp, self.relative_humidity)
+    }
+
+    pub fn get_heat_index(&self) -> Temperature {
+        derived::heat_index(self.air_temp, self.relative_humidity)
+    }
+
+    pub fn get_wind_chill(&self) -> Temperature {
+        derived::wind_chill(self.air_temp, self.wind_avg)
+    }
+
+    pub fn get_feels_like(&self) -> Temperature {
+        derived::feels_like(self.air_temp, self.relative_humidity, self.wind_avg)
+    }
+
+    pub fn get_apparent_temperature(&self) -> Temperature {
+        derived::apparent_temperature(self.air_temp, self.relative_humidity, self.wind_avg)
+    }
+
+    pub fn get_wet_bulb(&self) -> Temperature {
+        derived::wet_bulb(self.air_temp, self.relative_humidity)
+    }
+
+    /// Absolute humidity in g/m^3.
+    pub fn get_absolute_humidity(&self) -> f32 {
+        derived::absolute_humidity(self.air_temp, self.relative_humidity)
+    }
+
+    pub fn get_derived_metrics(&self) -> DerivedMetrics {
+        DerivedMetrics::new(self.air_temp, self.relative_humidity, self.wind_avg)
+    }
+
+    /// Sea-level pressure, for a station installed as described.
+    pub fn get_sea_level_pressure(&self, station: &StationConfig) -> Pressure {
+        Pressure::new(
+            station::sea_level_pressure(self.station_pressure, station.elevation, self.air_temp),
+            PressureUnit::Millibars,
+        )
+    }
+
+    /// Altimeter setting, for a station installed as described.
+    pub fn get_altimeter_setting(&self, station: &StationConfig) -> Pressure {
+        Pressure::new(
+            station::altimeter_setting(self.station_pressure, station.elevation),
+            PressureUnit::Millibars,
+        )
+    }
+
+    /// A copy of this record with every measurement converted to the given units, for output
+    /// that can't carry units alongside the values, like JSON. It shouldn't be stored.
+    pub fn in_units(&self, units: &UnitPreferences) -> Weather {
+        Weather {
+            wind_lull: units.speed(self.get_wind_lull()).value(),
+            wind_avg: units.speed(self.get_wind_avg()).value(),
+            wind_gust: units.speed(self.get_wind_gust()).value(),
+            station_pressure: units.pressure(self.get_station_pressure()).value(),
+            air_temp: units.temperature(self.get_air_temp()).value(),
+            rain_over_prev_minute: units
+                .precipitation(self.get_rain_over_prev_minute())
+                .value(),
+            lightning_avg_distance: self
+                .get_lightning_avg_distance()
+                .map(|distance| units.distance(distance).value().round() as u32),
+            illuminance: units.illuminance(self.get_illuminance()).value().round() as u32,
+            ..self.clone()
+        }
+    }
+
+    /// Formats the observation, with any extra details set on the returned `WeatherDisplay`.
+    /// Formatting the `Weather` directly is the same as formatting this with nothing set.
+    pub fn display(&self) -> WeatherDisplay<'_> {
+        WeatherDisplay {
+            weather: self,
+            station: None,
+            pressure_trend: None,
+            units: UnitPreferences::default(),
+        }
+    }
 }
 
 pub trait IntoWeather {
+    #[allow(clippy::wrong_self_convention)]
     fn into_weather(&self) -> Option<Weather>;
 }
 
+/// A single 3-second wind sample from a `rapid_wind` packet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RapidWind {
+    pub time_epoch: u64,
+    pub wind_speed: f32,
+    pub wind_direction: u16,
+    pub serial_number: String,
+}
+
+impl RapidWind {
+    pub fn get_time(&self) -> LocalResult<DateTime<Local>> {
+        Local.timestamp_opt(self.time_epoch as i64, 0)
+    }
+
+    pub fn get_wind_speed(&self) -> Speed {
+        Speed::new(self.wind_speed, SpeedUnit::MetersPerSecond)
+    }
+
+    pub fn get_wind_direction(&self) -> WindDirection {
+        WindDirection::from_sample(self.wind_speed, self.wind_direction)
+    }
+}
+
+pub trait IntoRapidWind {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_rapid_wind(&self) -> Option<RapidWind>;
+}
+
+/// The half of a `Weather` record reported by a legacy AIR device in an `obs_air` packet.
+#[derive(Debug, Clone)]
+pub struct AirWeather {
+    pub serial_number: String,
+    pub hub_sn: String,
+    pub time_epoch: u64,
+    pub station_pressure: f32,
+    pub air_temp: f32,
+    pub relative_humidity: f32,
+    pub lightning_strike_count: u32,
+    /// Null when no lightning was detected.
+    pub lightning_avg_distance: Option<u32>,
+    pub battery_voltage: f32,
+    pub report_interval: u16,
+}
+
+/// The half of a `Weather` record reported by a legacy SKY device in an `obs_sky` packet.
+#[derive(Debug, Clone)]
+pub struct SkyWeather {
+    pub serial_number: String,
+    pub hub_sn: String,
+    pub time_epoch: u64,
+    pub illuminance: u32,
+    pub uv_index: f32,
+    pub rain_over_prev_minute: f32,
+    pub wind_lull: f32,
+    pub wind_avg: f32,
+    pub wind_gust: f32,
+    pub wind_direction: u16,
+    pub battery_voltage: f32,
+    pub report_interval: u16,
+    pub solar_radiation: u32,
+    pub precip_type: PrecipitationType,
+    pub wind_sample_interval: u16,
+}
+
+#[derive(Debug, Clone)]
+pub enum PartialWeather {
+    Air(AirWeather),
+    Sky(SkyWeather),
+}
+
+pub trait IntoPartialWeather {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_partial_weather(&self) -> Option<PartialWeather>;
+}
+
+impl Weather {
+    /// Combines the AIR and SKY halves of an observation into a complete record. The later of the
+    /// two timestamps is used, and the lower of the two battery voltages is reported, since that's
+    /// the one that matters.
+    pub fn from_parts(air: &AirWeather, sky: &SkyWeather) -> Weather {
+        Weather {
+            time_epoch: air.time_epoch.max(sky.time_epoch),
+            wind_lull: sky.wind_lull,
+            wind_avg: sky.wind_avg,
+            wind_gust: sky.wind_gust,
+            wind_direction: sky.wind_direction,
+            wind_sample_interval: sky.wind_sample_interval,
+            station_pressure: air.station_pressure,
+            air_temp: air.air_temp,
+            relative_humidity: air.relative_humidity,
+            illuminance: sky.illuminance,
+            uv_index: sky.uv_index,
+            solar_radiation: sky.solar_radiation,
+            rain_over_prev_minute: sky.rain_over_prev_minute,
+            precip_type: sky.precip_type,
+            lightning_avg_distance: air.lightning_avg_distance,
+            lightning_strike_count: air.lightning_strike_count,
+            battery_voltage: air.battery_voltage.min(sky.battery_voltage),
+            report_interval: air.report_interval.max(sky.report_interval),
+            serial_number: format!("{}/{}", air.serial_number, sky.serial_number),
+            hub_sn: air.hub_sn.clone(),
+        }
+    }
+}
+
+/**
+An observation along with everything derived from it, for JSON output. The sea-level pressures are
+only included when the station's elevation is known, and the pressure trend when it's been looked
+up. If units are given, every measurement is converted to them and they're listed in `units`;
+otherwise measurements are in the SI units they're stored in.
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct WeatherReport {
+    #[serde(flatten)]
+    pub weather: Weather,
+    pub derived: DerivedMetrics,
+    pub wind: WindSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sea_level_pressure: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altimeter_setting: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure_trend: Option<PressureTrend>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<UnitPreferences>,
+}
+
+impl WeatherReport {
+    pub fn new(
+        weather: &Weather,
+        station: Option<&StationConfig>,
+        pressure_trend: Option<&PressureTrend>,
+        units: Option<&UnitPreferences>,
+    ) -> Self {
+        let pressure =
+            |pressure: Pressure| units.map_or(pressure, |units| units.pressure(pressure));
+
+        Self {
+            weather: units.map_or_else(|| weather.clone(), |units| weather.in_units(units)),
+            derived: units.map_or(weather.get_derived_metrics(), |units| {
+                weather.get_derived_metrics().in_units(units)
+            }),
+            wind: WindSummary::new(weather.get_wind_direction(), weather.get_beaufort()),
+            sea_level_pressure: station
+                .map(|station| pressure(weather.get_sea_level_pressure(station)).value()),
+            altimeter_setting: station
+                .map(|station| pressure(weather.get_altimeter_setting(station)).value()),
+            pressure_trend: pressure_trend
+                .map(|trend| units.map_or(*trend, |units| trend.in_units(units))),
+            units: units.copied(),
+        }
+    }
+}
+
+impl PartialWeather {
+    fn serial_number(&self) -> &str {
+        match self {
+            PartialWeather::Air(air) => &air.serial_number,
+            PartialWeather::Sky(sky) => &sky.serial_number,
+        }
+    }
+
+    fn hub_sn(&self) -> &str {
+        match self {
+            PartialWeather::Air(air) => &air.hub_sn,
+            PartialWeather::Sky(sky) => &sky.hub_sn,
+        }
+    }
+}
+
+/// The AIR and SKY devices seen relaying through a hub.
+#[derive(Default)]
+struct HubDevices {
+    air: HashSet<String>,
+    sky: HashSet<String>,
+}
+
+/**
+Pairs up `obs_air` and `obs_sky` observations into complete `Weather` records.
+
+Each device's latest half is held until its counterpart from the same report interval arrives; a
+half whose counterpart never shows up is replaced by the device's next one. An AIR is paired with
+the SKY it's been paired with explicitly or, failing that, with the only SKY relayed by the same
+hub. When a hub relays more than one AIR or SKY, which belong together can't be told from the
+packets, so only explicitly paired devices are merged.
+*/
+#[derive(Default)]
+pub struct WeatherMerger {
+    /// Each explicitly paired device's partner, in both directions.
+    pairs: HashMap<String, String>,
+    hubs: HashMap<String, HubDevices>,
+    /// The latest half from each device that hasn't been merged yet.
+    pending: HashMap<String, PartialWeather>,
+}
+
+impl WeatherMerger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A merger that pairs these AIR and SKY serial numbers, given as (AIR, SKY), with each other.
+    pub fn with_pairs<I: IntoIterator<Item = (String, String)>>(pairs: I) -> Self {
+        let mut merger = Self::default();
+
+        for (air, sky) in pairs {
+            merger.pairs.insert(air.clone(), sky.clone());
+            merger.pairs.insert(sky, air);
+        }
+
+        merger
+    }
+
+    /// The serial number of the device whose half goes with this one, if it can be told.
+    fn partner(&self, part: &PartialWeather) -> Option<String> {
+        if let Some(partner) = self.pairs.get(part.serial_number()) {
+            return Some(partner.clone());
+        }
+
+        let devices = self.hubs.get(part.hub_sn())?;
+
+        if devices.air.len() != 1 || devices.sky.len() != 1 {
+            return None;
+        }
+
+        let others = match part {
+            PartialWeather::Air(_) => &devices.sky,
+            PartialWeather::Sky(_) => &devices.air,
+        };
+
+        others
+            .iter()
+            .next()
+            .filter(|partner| !self.pairs.contains_key(*partner))
+            .cloned()
+    }
+
+    /// Adds one half of an observation, returning the complete record if the other half from the
+    /// same interval has already been seen.
+    pub fn merge(&mut self, part: PartialWeather) -> Option<Weather> {
+        let devices = self.hubs.entry(part.hub_sn().to_string()).or_default();
+
+        match &part {
+            PartialWeather::Air(air) => devices.air.insert(air.serial_number.clone()),
+            PartialWeather::Sky(sky) => devices.sky.insert(sky.serial_number.clone()),
+        };
+
+        let partner = self.partner(&part);
+        let counterpart = partner
+            .as_ref()
+            .and_then(|partner| self.pending.get(partner));
+        let weather = match (&part, counterpart) {
+            (PartialWeather::Air(air), Some(PartialWeather::Sky(sky)))
+            | (PartialWeather::Sky(sky), Some(PartialWeather::Air(air))) => {
+                // Both devices report once per interval, but not at exactly the same second.
+                let window = 60 * air.report_interval.max(sky.report_interval).max(1) as u64;
+
+                (air.time_epoch.abs_diff(sky.time_epoch) < window)
+                    .then(|| Weather::from_parts(air, sky))
+            }
+            _ => None,
+        };
+
+        match weather {
+            Some(weather) => {
+                if let Some(partner) = partner {
+                    self.pending.remove(&partner);
+                }
+
+                Some(weather)
+            }
+            None => {
+                self.pending.insert(part.serial_number().to_string(), part);
+                None
+            }
+        }
+    }
+}
+
+/// Formats a `Weather` record, optionally with the details that depend on where the station is.
+pub struct WeatherDisplay<'a> {
+    weather: &'a Weather,
+    station: Option<&'a StationConfig>,
+    pressure_trend: Option<&'a PressureTrend>,
+    units: UnitPreferences,
+}
+
+impl<'a> WeatherDisplay<'a> {
+    /// Includes sea-level pressure and altimeter setting for a station installed as described.
+    pub fn with_station(mut self, station: Option<&'a StationConfig>) -> Self {
+        self.station = station;
+        self
+    }
+
+    /// Shows measurements in the given units.
+    pub fn with_units(mut self, units: &UnitPreferences) -> Self {
+        self.units = *units;
+        self
+    }
+
+    /// Includes the pressure trend leading up to the observation.
+    pub fn with_pressure_trend(mut self, pressure_trend: Option<&'a PressureTrend>) -> Self {
+        self.pressure_trend = pressure_trend;
+        self
+    }
+}
+
 impl Display for Weather {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let obs_time = self.get_time().unwrap();
+        write!(f, "{}", self.display())
+    }
+}
+
+impl Display for WeatherDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let weather = self.weather;
+        let units = &self.units;
+        let obs_time = weather.get_time().unwrap();
         let elapsed = Local::now().signed_duration_since(obs_time);
         let display_time = obs_time.format("%B %-d, %Y at %-I:%M %p");
 
         writeln!(f, "{} ({} ago)", display_time, format_duration(elapsed))?;
-        writeln!(f, "Air Temperature: {}", self.get_air_temp().into_f())?;
         writeln!(
             f,
-            "Wind Lull: {}",
-            self.get_wind_lull().into_miles_per_hour()
+            "Station: {} (hub {})",
+            weather.serial_number, weather.hub_sn
+        )?;
+        writeln!(
+            f,
+            "Air Temperature: {}",
+            units.temperature(weather.get_air_temp())
+        )?;
+        writeln!(
+            f,
+            "Feels Like: {}",
+            units.temperature(weather.get_feels_like())
         )?;
-        writeln!(f, "Wind Avg: {}", self.get_wind_avg().into_miles_per_hour())?;
         writeln!(
             f,
-            "Wind Gust: {}",
-            self.get_wind_gust().into_miles_per_hour()
+            "Apparent Temperature: {}",
+            units.temperature(weather.get_apparent_temperature())
         )?;
-        writeln!(f, "Wind Direction: {}°", self.wind_direction)?;
+        writeln!(
+            f,
+            "Heat Index: {}",
+            units.temperature(weather.get_heat_index())
+        )?;
+        writeln!(
+            f,
+            "Wind Chill: {}",
+            units.temperature(weather.get_wind_chill())
+        )?;
+        writeln!(
+            f,
+            "Dew Point: {}",
+            units.temperature(weather.get_dew_point())
+        )?;
+        writeln!(f, "Wet Bulb: {}", units.temperature(weather.get_wet_bulb()))?;
+        writeln!(f, "Wind Lull: {}", units.speed(weather.get_wind_lull()))?;
+        writeln!(f, "Wind Avg: {}", units.speed(weather.get_wind_avg()))?;
+        writeln!(f, "Wind Gust: {}", units.speed(weather.get_wind_gust()))?;
+        writeln!(f, "Wind Direction: {}", weather.get_wind_direction())?;
+        writeln!(f, "Beaufort: {}", weather.get_beaufort())?;
         writeln!(
             f,
             "Wind Sample Interval: {} seconds",
-            self.wind_sample_interval
+            weather.wind_sample_interval
+        )?;
+        writeln!(
+            f,
+            "Station Pressure: {}",
+            units.pressure(weather.get_station_pressure())
+        )?;
+
+        if let Some(station) = self.station {
+            writeln!(
+                f,
+                "Sea Level Pressure: {}",
+                units.pressure(weather.get_sea_level_pressure(station))
+            )?;
+            writeln!(
+                f,
+                "Altimeter Setting: {}",
+                units.pressure(weather.get_altimeter_setting(station))
+            )?;
+        }
+
+        if let Some(pressure_trend) = self.pressure_trend {
+            writeln!(f, "Pressure Trend: {}", pressure_trend.with_units(units))?;
+        }
+
+        writeln!(f, "Relative Humidity: {}%", weather.relative_humidity)?;
+        writeln!(
+            f,
+            "Absolute Humidity: {:.1} g/m^3",
+            weather.get_absolute_humidity()
         )?;
-        writeln!(f, "Station Pressure: {} mbar", self.station_pressure)?;
-        writeln!(f, "Relative Humidity: {}%", self.relative_humidity)?;
-        writeln!(f, "Illuminance: {} Lux", self.illuminance)?;
-        writeln!(f, "UV Index: {}", self.uv_index)?;
-        writeln!(f, "Solar Radiation: {} W/m^2", self.solar_radiation)?;
         writeln!(
             f,
-            "Rain over Previous Minute: {} mm",
-            self.rain_over_prev_minute
+            "Illuminance: {}",
+            units.illuminance(weather.get_illuminance())
         )?;
-        writeln!(f, "Precipitation Type: {:?}", self.precip_type)?;
+        writeln!(f, "UV Index: {}", weather.uv_index)?;
+        writeln!(f, "Solar Radiation: {} W/m^2", weather.solar_radiation)?;
         writeln!(
             f,
-            "Lightning Average Distance: {} km",
-            self.lightning_avg_distance
+            "Rain over Previous Minute: {}",
+            units.precipitation(weather.get_rain_over_prev_minute())
         )?;
-        writeln!(f, "Lightning Strike Count: {}", self.lightning_strike_count)?;
-        writeln!(f, "Battery Voltage: {} Volts", self.battery_voltage)?;
-        writeln!(f, "Report Interval: {} Minutes", self.report_interval)?;
+        writeln!(f, "Precipitation Type: {:?}", weather.precip_type)?;
+        match weather.get_lightning_avg_distance() {
+            Some(distance) => writeln!(
+                f,
+                "Lightning Average Distance: {}",
+                units.distance(distance)
+            )?,
+            None => writeln!(f, "Lightning Average Distance: None")?,
+        }
+        writeln!(
+            f,
+            "Lightning Strike Count: {}",
+            weather.lightning_strike_count
+        )?;
+        writeln!(f, "Battery Voltage: {} Volts", weather.battery_voltage)?;
+        writeln!(f, "Report Interval: {} Minutes", weather.report_interval)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+
+    fn air(serial_number: &str, hub_sn: &str, time_epoch: u64, air_temp: f32) -> PartialWeather {
+        PartialWeather::Air(AirWeather {
+            serial_number: serial_number.to_string(),
+            hub_sn: hub_sn.to_string(),
+            time_epoch,
+            station_pressure: 1000.0,
+            air_temp,
+            relative_humidity: 50.0,
+            lightning_strike_count: 0,
+            lightning_avg_distance: None,
+            battery_voltage: 3.4,
+            report_interval: 1,
+        })
+    }
+
+    fn sky(serial_number: &str, hub_sn: &str, time_epoch: u64, wind_avg: f32) -> PartialWeather {
+        PartialWeather::Sky(SkyWeather {
+            serial_number: serial_number.to_string(),
+            hub_sn: hub_sn.to_string(),
+            time_epoch,
+            illuminance: 0,
+            uv_index: 0.0,
+            rain_over_prev_minute: 0.0,
+            wind_lull: 0.0,
+            wind_avg,
+            wind_gust: 0.0,
+            wind_direction: 0,
+            battery_voltage: 3.3,
+            report_interval: 1,
+            solar_radiation: 0,
+            precip_type: PrecipitationType::None,
+            wind_sample_interval: 3,
+        })
+    }
+
+    #[test]
+    fn merges_a_hubs_only_air_and_sky() {
+        let mut merger = WeatherMerger::new();
+
+        assert!(merger.merge(air("AR-1", "HB-1", 1000, 20.0)).is_none());
+
+        let weather = merger.merge(sky("SK-1", "HB-1", 1010, 4.0)).unwrap();
+
+        assert_eq!(weather.time_epoch, 1010);
+        assert_eq!(weather.air_temp, 20.0);
+        assert_eq!(weather.wind_avg, 4.0);
+        assert_eq!(weather.battery_voltage, 3.3);
+        assert_eq!(weather.serial_number, "AR-1/SK-1");
+    }
+
+    #[test]
+    fn does_not_merge_halves_from_different_intervals() {
+        let mut merger = WeatherMerger::new();
+
+        merger.merge(air("AR-1", "HB-1", 1000, 20.0));
+        assert!(merger.merge(sky("SK-1", "HB-1", 1060, 4.0)).is_none());
+
+        let weather = merger.merge(air("AR-1", "HB-1", 1065, 21.0)).unwrap();
+
+        assert_eq!(weather.air_temp, 21.0);
+    }
+
+    #[test]
+    fn does_not_guess_pairs_on_a_hub_with_two_of_each() {
+        let mut merger = WeatherMerger::new();
+
+        merger.merge(air("AR-1", "HB-1", 1000, 20.0));
+        merger.merge(air("AR-2", "HB-1", 1000, 10.0));
+        merger.merge(sky("SK-2", "HB-1", 1000, 8.0));
+        assert!(merger.merge(sky("SK-1", "HB-1", 1000, 4.0)).is_none());
+    }
+
+    #[test]
+    fn merges_explicit_pairs_on_a_shared_hub() {
+        let mut merger = WeatherMerger::with_pairs([
+            ("AR-1".to_string(), "SK-1".to_string()),
+            ("AR-2".to_string(), "SK-2".to_string()),
+        ]);
+
+        merger.merge(air("AR-1", "HB-1", 1000, 20.0));
+        merger.merge(air("AR-2", "HB-1", 1000, 10.0));
+
+        let second = merger.merge(sky("SK-2", "HB-1", 1000, 8.0)).unwrap();
+        let first = merger.merge(sky("SK-1", "HB-1", 1000, 4.0)).unwrap();
+
+        assert_eq!((second.air_temp, second.wind_avg), (10.0, 8.0));
+        assert_eq!((first.air_temp, first.wind_avg), (20.0, 4.0));
+    }
+
+    #[test]
+    fn parses_obs_air_with_null_fields() {
+        let packet: Packet = serde_json::from_str(
+            r#"{"serial_number":"AR-1","type":"obs_air","hub_sn":"HB-1","firmware_revision":17,
+            "obs":[[1493164835,835.0,10.0,45,0,null,3.46,1]]}"#,
+        )
+        .unwrap();
+
+        match packet.into_partial_weather() {
+            Some(PartialWeather::Air(air)) => {
+                assert_eq!(air.air_temp, 10.0);
+                assert_eq!(air.lightning_avg_distance, None);
+            }
+            other => panic!("expected an AIR half, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drops_halves_missing_required_fields() {
+        let air: Packet = serde_json::from_str(
+            r#"{"serial_number":"AR-1","type":"obs_air","hub_sn":"HB-1","firmware_revision":17,
+            "obs":[[1493164835,835.0,null,45,0,null,3.46,1]]}"#,
+        )
+        .unwrap();
+        let sky: Packet = serde_json::from_str(
+            r#"{"serial_number":"SK-1","type":"obs_sky","hub_sn":"HB-1","firmware_revision":29,
+            "obs":[[null,9000,10,0.0,2.6,4.6,7.4,187,3.12,1,130,null,0,3]]}"#,
+        )
+        .unwrap();
+
+        assert!(air.into_partial_weather().is_none());
+        assert!(sky.into_partial_weather().is_none());
+    }
+
+    #[test]
+    fn deserializes_rows_without_serial_numbers() {
+        let row = serde_json::json!({
+            "id": 1,
+            "time_epoch": 1000,
+            "wind_lull": 0.0,
+            "wind_avg": 1.0,
+            "wind_gust": 2.0,
+            "wind_direction": 90,
+            "wind_sample_interval": 3,
+            "station_pressure": 1000.0,
+            "air_temp": 20.0,
+            "relative_humidity": 50.0,
+            "illuminance": 0,
+            "uv_index": 0.0,
+            "solar_radiation": 0,
+            "rain_over_prev_minute": 0.0,
+            "precip_type": 0,
+            "lightning_avg_distance": null,
+            "lightning_strike_count": 0,
+            "battery_voltage": 2.6,
+            "report_interval": 1,
+            "serial_number": null,
+            "hub_sn": null,
+        });
+
+        let weather: Weather = serde_json::from_value(row.clone()).unwrap();
+        assert_eq!(weather.serial_number, "");
+        assert_eq!(weather.hub_sn, "");
+
+        let mut row = row;
+        let fields = row.as_object_mut().unwrap();
+        fields.remove("serial_number");
+        fields.insert("hub_sn".to_string(), "HB-1".into());
+
+        let weather: Weather = serde_json::from_value(row).unwrap();
+        assert_eq!(weather.serial_number, "");
+        assert_eq!(weather.hub_sn, "HB-1");
+    }
+}
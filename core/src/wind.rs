@@ -0,0 +1,270 @@
+use crate::units::{Speed, SpeedUnit};
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+
+/// One of the 16 points of the compass, each covering 22.5° centered on its direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompassPoint {
+    N,
+    NNE,
+    NE,
+    ENE,
+    E,
+    ESE,
+    SE,
+    SSE,
+    S,
+    SSW,
+    SW,
+    WSW,
+    W,
+    WNW,
+    NW,
+    NNW,
+}
+
+impl CompassPoint {
+    pub const ALL: [CompassPoint; 16] = [
+        CompassPoint::N,
+        CompassPoint::NNE,
+        CompassPoint::NE,
+        CompassPoint::ENE,
+        CompassPoint::E,
+        CompassPoint::ESE,
+        CompassPoint::SE,
+        CompassPoint::SSE,
+        CompassPoint::S,
+        CompassPoint::SSW,
+        CompassPoint::SW,
+        CompassPoint::WSW,
+        CompassPoint::W,
+        CompassPoint::WNW,
+        CompassPoint::NW,
+        CompassPoint::NNW,
+    ];
+
+    pub fn from_degrees(degrees: u16) -> Self {
+        // Shifting by half a point puts each point's range starting at a multiple of 22.5°.
+        let index = ((degrees % 360) as f32 + 11.25) / 22.5;
+
+        CompassPoint::ALL[index as usize % 16]
+    }
+
+    /// The point's full name, e.g. "south-southwest".
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompassPoint::N => "north",
+            CompassPoint::NNE => "north-northeast",
+            CompassPoint::NE => "northeast",
+            CompassPoint::ENE => "east-northeast",
+            CompassPoint::E => "east",
+            CompassPoint::ESE => "east-southeast",
+            CompassPoint::SE => "southeast",
+            CompassPoint::SSE => "south-southeast",
+            CompassPoint::S => "south",
+            CompassPoint::SSW => "south-southwest",
+            CompassPoint::SW => "southwest",
+            CompassPoint::WSW => "west-southwest",
+            CompassPoint::W => "west",
+            CompassPoint::WNW => "west-northwest",
+            CompassPoint::NW => "northwest",
+            CompassPoint::NNW => "north-northwest",
+        }
+    }
+}
+
+impl Display for CompassPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Below this speed in m/s, which rounds to zero knots, the wind is reported as calm.
+pub const CALM_THRESHOLD: f32 = 0.5;
+
+/// Up to this speed in m/s (3 knots), light wind whose lull drops to zero is reported as
+/// variable.
+pub const VARIABLE_THRESHOLD: f32 = 1.5;
+
+/**
+The direction the wind is blowing from, as it should be reported.
+
+A direction means nothing when there's no wind, so calm wind has none, even though the devices
+still report the last one they measured. Light wind that died away completely at some point in the
+report interval has probably shifted around, and is reported as variable, as in METARs.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindDirection {
+    Calm,
+    Variable,
+    From { degrees: u16, point: CompassPoint },
+}
+
+impl WindDirection {
+    /// The direction of a single wind sample, which can be calm but not variable.
+    pub fn from_sample(speed: f32, degrees: u16) -> Self {
+        if speed < CALM_THRESHOLD {
+            WindDirection::Calm
+        } else {
+            WindDirection::From {
+                degrees,
+                point: CompassPoint::from_degrees(degrees),
+            }
+        }
+    }
+
+    /// The direction of the wind over a report interval with the given average and lull speeds.
+    pub fn from_interval(avg: f32, lull: f32, degrees: u16) -> Self {
+        if (CALM_THRESHOLD..=VARIABLE_THRESHOLD).contains(&avg) && lull <= 0.0 {
+            WindDirection::Variable
+        } else {
+            WindDirection::from_sample(avg, degrees)
+        }
+    }
+}
+
+impl Display for WindDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindDirection::Calm => write!(f, "Calm"),
+            WindDirection::Variable => write!(f, "Variable"),
+            WindDirection::From { degrees, point } => write!(f, "{} ({}°)", point, degrees),
+        }
+    }
+}
+
+/// Serializes as "calm", "variable", or the compass point.
+impl Serialize for WindDirection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            WindDirection::Calm => serializer.serialize_str("calm"),
+            WindDirection::Variable => serializer.serialize_str("variable"),
+            WindDirection::From { point, .. } => serializer.serialize_str(&point.to_string()),
+        }
+    }
+}
+
+/// A force on the Beaufort scale, from 0 (calm) to 12 (hurricane force).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Beaufort(pub u8);
+
+impl Beaufort {
+    /**
+    The force of wind at the given speed in m/s.
+
+    This rounds the same empirical relation `SpeedUnit::Beaufort` uses, so a speed shown as
+    "4 Bft" is always force 4. Rounding it reproduces the WMO's table to within 0.1 m/s.
+    */
+    pub fn from_speed(speed: f32) -> Self {
+        let force = Speed::new(speed, SpeedUnit::MetersPerSecond)
+            .to(SpeedUnit::Beaufort)
+            .value()
+            .round();
+
+        Beaufort(force.min(12.0) as u8)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.0 {
+            0 => "Calm",
+            1 => "Light air",
+            2 => "Light breeze",
+            3 => "Gentle breeze",
+            4 => "Moderate breeze",
+            5 => "Fresh breeze",
+            6 => "Strong breeze",
+            7 => "Near gale",
+            8 => "Gale",
+            9 => "Strong gale",
+            10 => "Storm",
+            11 => "Violent storm",
+            _ => "Hurricane force",
+        }
+    }
+}
+
+impl Display for Beaufort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Force {} ({})", self.0, self.description())
+    }
+}
+
+/// How the wind over an observation should be described, for JSON output.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WindSummary {
+    pub direction: WindDirection,
+    pub beaufort: Beaufort,
+    pub beaufort_description: &'static str,
+}
+
+impl WindSummary {
+    pub fn new(direction: WindDirection, beaufort: Beaufort) -> Self {
+        Self {
+            direction,
+            beaufort,
+            beaufort_description: beaufort.description(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_nearest_compass_point() {
+        assert_eq!(CompassPoint::from_degrees(0), CompassPoint::N);
+        assert_eq!(CompassPoint::from_degrees(11), CompassPoint::N);
+        assert_eq!(CompassPoint::from_degrees(12), CompassPoint::NNE);
+        assert_eq!(CompassPoint::from_degrees(202), CompassPoint::SSW);
+        assert_eq!(CompassPoint::from_degrees(348), CompassPoint::NNW);
+        assert_eq!(CompassPoint::from_degrees(349), CompassPoint::N);
+        assert_eq!(CompassPoint::from_degrees(360), CompassPoint::N);
+        assert_eq!(CompassPoint::from_degrees(450), CompassPoint::E);
+    }
+
+    #[test]
+    fn reports_calm_and_variable_wind() {
+        assert_eq!(WindDirection::from_sample(0.4, 90), WindDirection::Calm);
+        assert_eq!(
+            WindDirection::from_interval(1.2, 0.0, 90),
+            WindDirection::Variable
+        );
+        assert_eq!(
+            WindDirection::from_interval(1.2, 0.3, 90),
+            WindDirection::From {
+                degrees: 90,
+                point: CompassPoint::E
+            }
+        );
+        assert_eq!(
+            WindDirection::from_interval(0.2, 0.0, 90),
+            WindDirection::Calm
+        );
+    }
+
+    #[test]
+    fn matches_the_wmo_beaufort_table() {
+        // The lowest speed of each force from 1 to 12 in the WMO's table, in m/s.
+        let lower_bounds = [
+            0.3, 1.6, 3.4, 5.5, 8.0, 10.8, 13.9, 17.2, 20.8, 24.5, 28.5, 32.7,
+        ];
+
+        for (force, bound) in lower_bounds.into_iter().enumerate() {
+            assert_eq!(Beaufort::from_speed(bound - 0.1), Beaufort(force as u8));
+            assert_eq!(Beaufort::from_speed(bound), Beaufort(force as u8 + 1));
+        }
+        assert_eq!(Beaufort::from_speed(60.0), Beaufort(12));
+    }
+
+    #[test]
+    fn agrees_with_beaufort_speeds() {
+        for force in 0..=12 {
+            let speed = Speed::new(force as f32, SpeedUnit::Beaufort).into_meters_per_second();
+            assert_eq!(Beaufort::from_speed(speed.value()), Beaufort(force));
+        }
+    }
+}
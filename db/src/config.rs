@@ -0,0 +1,182 @@
+use core::station::StationConfig;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+
+use crate::Error;
+
+/// Settings read from `config.toml`. The file is looked for at `$TEMPESTRS_CONFIG`, then in the
+/// user's config directory (`~/.config/tempestrs` on Linux), then in `/etc/tempestrs`. Every
+/// setting is optional, and a missing file is the same as an empty one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Which database to use, in any of the forms `DatabaseLocation` accepts.
+    pub database: Option<String>,
+    /// Which units to show measurements in, in the form `UnitPreferences` parses.
+    pub units: Option<String>,
+    /// Where the station is installed, needed for sea-level pressure.
+    pub station: Option<StationConfig>,
+    /// Where the listener listens and which packets it accepts.
+    pub listener: Option<ListenerConfig>,
+}
+
+/// The `[listener]` section of the config file. Command-line options override each setting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// Addresses to listen on, each an IPv4 or IPv6 address with an optional port, e.g.
+    /// "0.0.0.0:50222", "192.168.10.255" or "[::]". Hub broadcasts only arrive at "0.0.0.0" or
+    /// the broadcast address they were sent to, not at an interface's own address.
+    #[serde(default)]
+    pub bind: Vec<String>,
+    /// The port for addresses given without one.
+    pub port: Option<u16>,
+    /// Serial numbers of the devices and hubs to accept packets from. Packets are accepted if
+    /// either their device or their hub is listed; an empty list accepts everything.
+    #[serde(default)]
+    pub serials: Vec<String>,
+    /// Serial numbers of legacy AIR and SKY devices whose observations should be merged, as
+    /// ["AR-…", "SK-…"] pairs. Only needed when a hub relays more than one AIR or SKY; a hub's
+    /// only AIR and only SKY are paired automatically.
+    #[serde(default)]
+    pub pairs: Vec<(String, String)>,
+    /// The URL of the worker to forward observations to, e.g. "https://weather.example.com".
+    /// Observations are only stored locally if this isn't set.
+    pub upload_url: Option<String>,
+    /// How long to keep each kind of data, in the form `RetentionPolicy` parses, e.g.
+    /// "observation=365,rapid_wind=30". Nothing is pruned unless this or $TEMPESTRS_RETENTION
+    /// is set.
+    pub retention: Option<String>,
+    /// What to log, e.g. "info,packet::rapid_wind=off". See the listener's --log option.
+    pub log: Option<String>,
+    /// How to write log lines: "text" or "json".
+    pub log_format: Option<String>,
+}
+
+/// The port Tempest hubs broadcast to.
+pub const DEFAULT_PORT: u16 = 50222;
+
+impl ListenerConfig {
+    /// The addresses to listen on: `bind` if it isn't empty, then the `bind` setting, then
+    /// "0.0.0.0". Addresses without a port use `port`, then the `port` setting, then 50222.
+    pub fn bind_addresses(
+        &self,
+        bind: &[String],
+        port: Option<u16>,
+    ) -> Result<Vec<SocketAddr>, String> {
+        let port = port.or(self.port).unwrap_or(DEFAULT_PORT);
+        let bind = match (bind, self.bind.as_slice()) {
+            (bind, _) if !bind.is_empty() => bind.to_vec(),
+            (_, bind) if !bind.is_empty() => bind.to_vec(),
+            _ => vec!["0.0.0.0".to_string()],
+        };
+
+        bind.iter()
+            .map(|value| parse_bind_address(value, port))
+            .collect()
+    }
+
+    /// The serial numbers to accept packets from: `serials` if it isn't empty, then the
+    /// `serials` setting. Empty means everything is accepted.
+    pub fn accepted_serials(&self, serials: Vec<String>) -> Vec<String> {
+        if serials.is_empty() {
+            self.serials.clone()
+        } else {
+            serials
+        }
+    }
+}
+
+/// Parses an address to listen on, using `port` if the address doesn't include one.
+fn parse_bind_address(value: &str, port: u16) -> Result<SocketAddr, String> {
+    if let Ok(address) = value.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, port))
+        .map_err(|_| format!("invalid address to listen on: {}", value))
+}
+
+impl Config {
+    fn candidate_paths() -> Vec<PathBuf> {
+        if let Some(path) = env::var_os("TEMPESTRS_CONFIG") {
+            return vec![PathBuf::from(path)];
+        }
+
+        let mut paths = Vec::new();
+
+        if let Some(dir) = dirs::config_dir() {
+            paths.push(dir.join("tempestrs").join("config.toml"));
+        }
+
+        paths.push(PathBuf::from("/etc/tempestrs/config.toml"));
+        paths
+    }
+
+    /// Loads the first config file found, or the default config if there isn't one. A file named
+    /// by `$TEMPESTRS_CONFIG` must exist.
+    pub fn load() -> Result<Config, Error> {
+        let explicit = env::var_os("TEMPESTRS_CONFIG").is_some();
+
+        for path in Config::candidate_paths() {
+            if !explicit && !path.exists() {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&path)?;
+
+            return toml::from_str(&contents)
+                .map_err(|error| Error::Config(format!("{}: {}", path.display(), error)));
+        }
+
+        Ok(Config::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listens_on_every_interface_by_default() {
+        let config = ListenerConfig::default();
+
+        assert_eq!(
+            config.bind_addresses(&[], None),
+            Ok(vec!["0.0.0.0:50222".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn prefers_bind_addresses_given_over_the_config() {
+        let config = ListenerConfig {
+            bind: vec!["192.168.10.255".to_string()],
+            port: Some(50223),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.bind_addresses(&[], None),
+            Ok(vec!["192.168.10.255:50223".parse().unwrap()])
+        );
+        assert_eq!(
+            config.bind_addresses(
+                &["[::]".to_string(), "0.0.0.0:1234".to_string()],
+                Some(5000)
+            ),
+            Ok(vec![
+                "[::]:5000".parse().unwrap(),
+                "0.0.0.0:1234".parse().unwrap()
+            ])
+        );
+        assert!(config.bind_addresses(&["eth0".to_string()], None).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    /// No database location was configured, and there's no home directory to put one in.
+    NoDataDir,
+    /// The config file couldn't be parsed.
+    Config(String),
+    Io(std::io::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoDataDir => write!(
+                f,
+                "no data directory found; set TEMPESTRS_DB or pass --db to choose a database"
+            ),
+            Error::Config(message) => write!(f, "invalid config: {}", message),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Sqlite(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Sqlite(error)
+    }
+}
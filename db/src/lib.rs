@@ -1,32 +1,134 @@
 use core::{
-    queries::{QUERY_CREATE_TABLE_OBSERVATION, QUERY_INSERT_OBSERVATION},
-    weather::Weather,
+    event::{LightningStrike, RainStart},
+    outbox::OutboxEntry,
+    queries::{
+        pending_migrations, ObservationRange, SortOrder, QUERY_COUNT_OUTBOX,
+        QUERY_CREATE_TABLE_SCHEMA_VERSION, QUERY_DELETE_OUTBOX, QUERY_DELETE_ROLLUP_DAILY_FROM,
+        QUERY_DELETE_ROLLUP_HOURLY_FROM, QUERY_INSERT_DEVICE_STATUS, QUERY_INSERT_HUB_STATUS,
+        QUERY_INSERT_LIGHTNING_STRIKE, QUERY_INSERT_OBSERVATION, QUERY_INSERT_OUTBOX,
+        QUERY_INSERT_RAIN_START, QUERY_INSERT_RAPID_WIND, QUERY_INSERT_SCHEMA_VERSION,
+        QUERY_SELECT_DEVICE_STATUS_AFTER_ID, QUERY_SELECT_DEVICE_STATUS_RANGE,
+        QUERY_SELECT_HUB_STATUS_AFTER_ID, QUERY_SELECT_HUB_STATUS_RANGE, QUERY_SELECT_LATEST_IDS,
+        QUERY_SELECT_LIGHTNING_STRIKE_AFTER_ID, QUERY_SELECT_LIGHTNING_STRIKE_RANGE,
+        QUERY_SELECT_OBSERVATIONS, QUERY_SELECT_OBSERVATIONS_AFTER_ID,
+        QUERY_SELECT_OLDEST_OBSERVATION_TIME, QUERY_SELECT_OUTBOX, QUERY_SELECT_PRESSURE_SAMPLES,
+        QUERY_SELECT_RAIN_START_AFTER_ID, QUERY_SELECT_RAIN_START_RANGE, QUERY_SELECT_RAIN_TOTALS,
+        QUERY_SELECT_RAPID_WIND_AFTER_ID, QUERY_SELECT_RAPID_WIND_RANGE,
+        QUERY_SELECT_ROLLUP_DAILY_RAIN, QUERY_SELECT_SCHEMA_VERSION, QUERY_UPDATE_OUTBOX_FAILED,
+    },
+    rain::{RainPeriods, RainSums, RainTotals},
+    retention::{PruneEntry, PruneReport, RetainedTable, RetentionPolicy},
+    rollup::{Rollup, RollupPeriod},
+    status::{DeviceStatus, HubStatus},
+    trend::{PressureSample, PressureTrend},
+    util::now_epoch,
+    weather::{RapidWind, Weather},
 };
-use dirs::home_dir;
-use rusqlite::{params, Connection};
-use std::fs::create_dir;
+pub use rusqlite::Connection;
+use rusqlite::{params, Params, Row, TransactionBehavior};
+use std::fs::create_dir_all;
 
-pub fn connect() -> rusqlite::Result<Connection> {
-    let dir = home_dir().unwrap().join(".tempestrs");
+pub mod config;
+mod error;
+mod location;
 
-    if !dir.exists() {
-        create_dir(&dir).expect("unable to create directory");
-    }
+pub use error::Error;
+pub use location::DatabaseLocation;
+
+/// Opens the database at the given location, creating it and its directory if needed, and brings
+/// its schema up to date.
+pub fn connect(location: &DatabaseLocation) -> Result<Connection, Error> {
+    let mut conn = match location.path()? {
+        Some(path) => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                create_dir_all(dir)?;
+            }
 
-    let conn = Connection::open(dir.join("weather.db3"))?;
+            Connection::open(path)?
+        }
+        None => Connection::open_in_memory()?,
+    };
 
-    conn.execute(QUERY_CREATE_TABLE_OBSERVATION, ())?;
+    conn.migrate()?;
 
     Ok(conn)
 }
 
+/// Opens the database chosen by `flag`, the environment or the config file, as described in
+/// `DatabaseLocation::resolve`.
+pub fn open(flag: Option<&str>) -> Result<Connection, Error> {
+    let config = config::Config::load()?;
+
+    connect(&DatabaseLocation::resolve(flag, &config))
+}
+
+pub trait Migrate {
+    /// Returns the version of the newest migration applied to the database, or zero if none have
+    /// been.
+    fn schema_version(&self) -> rusqlite::Result<u32>;
+
+    /// Applies every pending migration in order, returning the resulting schema version.
+    fn migrate(&mut self) -> rusqlite::Result<u32>;
+}
+
+impl Migrate for Connection {
+    fn schema_version(&self) -> rusqlite::Result<u32> {
+        self.execute(QUERY_CREATE_TABLE_SCHEMA_VERSION, ())?;
+        self.query_row(QUERY_SELECT_SCHEMA_VERSION, (), |row| row.get(0))
+    }
+
+    fn migrate(&mut self) -> rusqlite::Result<u32> {
+        let mut version = self.schema_version()?;
+
+        for migration in pending_migrations(version) {
+            // Another process may be migrating the same database, so each step takes the write
+            // lock before checking whether it's still needed.
+            let tx = self.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+            version = tx.query_row(QUERY_SELECT_SCHEMA_VERSION, (), |row| row.get(0))?;
+
+            if version >= migration.version {
+                continue;
+            }
+
+            for statement in migration.statements {
+                tx.execute(statement, ())?;
+            }
+
+            tx.execute(
+                QUERY_INSERT_SCHEMA_VERSION,
+                params!(migration.version, migration.description, now_epoch()),
+            )?;
+            tx.commit()?;
+
+            version = migration.version;
+        }
+
+        Ok(version)
+    }
+}
+
+/// Runs an `INSERT ... ON CONFLICT DO NOTHING RETURNING id` statement, returning whether a row
+/// was actually inserted.
+fn insert_if_new<P: Params>(conn: &Connection, query: &str, params: P) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare_cached(query)?;
+    let mut rows = stmt.query(params)?;
+
+    Ok(rows.next()?.is_some())
+}
+
 pub trait InsertObservation {
-    fn insert_observation(&self, obs: Weather) -> rusqlite::Result<()>;
+    /// Stores an observation and folds it into the rollups, returning `false` without changing
+    /// anything if an observation from the same device at the same time is already stored.
+    fn insert_observation(&self, obs: &Weather) -> rusqlite::Result<bool>;
 }
 
 impl InsertObservation for Connection {
-    fn insert_observation(&self, obs: Weather) -> rusqlite::Result<()> {
-        self.execute(
+    fn insert_observation(&self, obs: &Weather) -> rusqlite::Result<bool> {
+        let tx = self.unchecked_transaction()?;
+
+        let inserted = insert_if_new(
+            &tx,
             QUERY_INSERT_OBSERVATION,
             params!(
                 obs.time_epoch,
@@ -47,48 +149,1159 @@ impl InsertObservation for Connection {
                 obs.lightning_strike_count,
                 obs.battery_voltage,
                 obs.report_interval,
+                obs.serial_number,
+                obs.hub_sn,
+            ),
+        )?;
+
+        if inserted {
+            update_rollups(&tx, obs)?;
+        }
+
+        tx.commit()?;
+
+        Ok(inserted)
+    }
+}
+
+/// Folds an observation into the hourly and daily rollups it belongs to.
+fn update_rollups(conn: &Connection, obs: &Weather) -> rusqlite::Result<()> {
+    let solar_energy = obs.solar_radiation as f32 * obs.report_interval as f32 / 60.0;
+
+    for period in [RollupPeriod::Hourly, RollupPeriod::Daily] {
+        conn.execute(
+            period.upsert_query(),
+            params!(
+                period.period_start(obs.time_epoch),
+                obs.serial_number,
+                obs.air_temp,
+                obs.relative_humidity,
+                obs.station_pressure,
+                obs.wind_avg,
+                obs.wind_gust,
+                obs.wind_direction,
+                obs.rain_over_prev_minute,
+                obs.uv_index,
+                solar_energy,
+                obs.lightning_strike_count,
             ),
         )?;
+    }
+
+    Ok(())
+}
+
+pub trait RebuildRollups {
+    /// Recomputes the rollups from the stored observations, returning how many observations were
+    /// folded in. Only rollups from the oldest stored observation onward are replaced, so those
+    /// whose observations have already been pruned are kept.
+    fn rebuild_rollups(&mut self) -> rusqlite::Result<usize>;
+}
+
+impl RebuildRollups for Connection {
+    fn rebuild_rollups(&mut self) -> rusqlite::Result<usize> {
+        let tx = self.transaction()?;
+
+        let range = ObservationRange {
+            order: SortOrder::Ascending,
+            ..Default::default()
+        };
+        let observations = tx.get_observations_in_range(&range)?;
+
+        if let Some(oldest) = observations.first() {
+            tx.execute(
+                QUERY_DELETE_ROLLUP_HOURLY_FROM,
+                params!(RollupPeriod::Hourly.period_start(oldest.time_epoch)),
+            )?;
+            tx.execute(
+                QUERY_DELETE_ROLLUP_DAILY_FROM,
+                params!(RollupPeriod::Daily.period_start(oldest.time_epoch)),
+            )?;
+        }
+
+        for obs in &observations {
+            update_rollups(&tx, obs)?;
+        }
+
+        tx.commit()?;
+
+        Ok(observations.len())
+    }
+}
+
+pub trait Prune {
+    /// Deletes everything older than the policy allows as of `now_epoch`, in a single
+    /// transaction. With `dry_run`, nothing is deleted and the report says what would have been.
+    /// SQLite reuses the freed pages, so the file stops growing rather than shrinking.
+    fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        now_epoch: u64,
+        dry_run: bool,
+    ) -> rusqlite::Result<PruneReport>;
+}
+
+impl Prune for Connection {
+    fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        now_epoch: u64,
+        dry_run: bool,
+    ) -> rusqlite::Result<PruneReport> {
+        let tx = self.unchecked_transaction()?;
+        let mut entries = Vec::new();
+
+        for table in RetainedTable::ALL {
+            let Some(cutoff_epoch) = policy.cutoff(table, now_epoch) else {
+                continue;
+            };
+
+            let rows = if dry_run {
+                tx.query_row(table.count_query(), params!(cutoff_epoch), |row| row.get(0))?
+            } else {
+                tx.execute(table.delete_query(), params!(cutoff_epoch))?
+            };
+
+            entries.push(PruneEntry {
+                table,
+                cutoff_epoch,
+                rows,
+            });
+        }
+
+        tx.commit()?;
+
+        Ok(PruneReport { dry_run, entries })
+    }
+}
+
+pub trait InsertRapidWind {
+    /// Returns whether the sample was new, rather than a duplicate of one already stored.
+    fn insert_rapid_wind(&self, sample: &RapidWind) -> rusqlite::Result<bool>;
+}
+
+impl InsertRapidWind for Connection {
+    fn insert_rapid_wind(&self, sample: &RapidWind) -> rusqlite::Result<bool> {
+        insert_if_new(
+            self,
+            QUERY_INSERT_RAPID_WIND,
+            params!(
+                sample.time_epoch,
+                sample.wind_speed,
+                sample.wind_direction,
+                sample.serial_number,
+            ),
+        )
+    }
+}
+
+pub trait InsertLightningStrike {
+    /// Returns whether the strike was new, rather than a duplicate of one already stored.
+    fn insert_lightning_strike(&self, strike: &LightningStrike) -> rusqlite::Result<bool>;
+}
+
+impl InsertLightningStrike for Connection {
+    fn insert_lightning_strike(&self, strike: &LightningStrike) -> rusqlite::Result<bool> {
+        insert_if_new(
+            self,
+            QUERY_INSERT_LIGHTNING_STRIKE,
+            params!(
+                strike.time_epoch,
+                strike.distance,
+                strike.energy,
+                strike.serial_number,
+            ),
+        )
+    }
+}
+
+pub trait InsertRainStart {
+    /// Returns whether the rain start was new, rather than a duplicate of one already stored.
+    fn insert_rain_start(&self, rain_start: &RainStart) -> rusqlite::Result<bool>;
+}
+
+impl InsertRainStart for Connection {
+    fn insert_rain_start(&self, rain_start: &RainStart) -> rusqlite::Result<bool> {
+        insert_if_new(
+            self,
+            QUERY_INSERT_RAIN_START,
+            params!(rain_start.time_epoch, rain_start.serial_number),
+        )
+    }
+}
+
+pub trait InsertDeviceStatus {
+    /// Returns whether the report was new, rather than a duplicate of one already stored.
+    fn insert_device_status(&self, status: &DeviceStatus) -> rusqlite::Result<bool>;
+}
+
+impl InsertDeviceStatus for Connection {
+    fn insert_device_status(&self, status: &DeviceStatus) -> rusqlite::Result<bool> {
+        insert_if_new(
+            self,
+            QUERY_INSERT_DEVICE_STATUS,
+            params!(
+                status.time_epoch,
+                status.serial_number,
+                status.hub_sn,
+                status.uptime,
+                status.voltage,
+                status.firmware_revision,
+                status.rssi,
+                status.hub_rssi,
+                status.sensor_status,
+                status.debug,
+            ),
+        )
+    }
+}
+
+pub trait InsertHubStatus {
+    /// Returns whether the report was new, rather than a duplicate of one already stored.
+    fn insert_hub_status(&self, status: &HubStatus) -> rusqlite::Result<bool>;
+}
+
+impl InsertHubStatus for Connection {
+    fn insert_hub_status(&self, status: &HubStatus) -> rusqlite::Result<bool> {
+        insert_if_new(
+            self,
+            QUERY_INSERT_HUB_STATUS,
+            params!(
+                status.time_epoch,
+                status.serial_number,
+                status.firmware_revision,
+                status.uptime,
+                status.rssi,
+                status.reset_flags,
+                status.seq,
+                status.radio_version,
+                status.reboot_count,
+                status.bus_error_count,
+                status.radio_status,
+                status.radio_network_id,
+            ),
+        )
+    }
+}
+
+/**
+Records waiting to be uploaded. Records are queued as they're stored and stay queued until the
+upload server accepts them, so nothing is lost while the network or the server is down.
+*/
+pub trait Outbox {
+    /// Queues the JSON `body` to be posted to `path`.
+    fn enqueue(&self, path: &str, body: &str) -> rusqlite::Result<()>;
+
+    /// Returns up to `limit` queued records, oldest first.
+    fn get_outbox(&self, limit: usize) -> rusqlite::Result<Vec<OutboxEntry>>;
+
+    /// Returns how many records are queued.
+    fn count_outbox(&self) -> rusqlite::Result<u64>;
+
+    /// Removes a record from the queue, once it's uploaded or can never be.
+    fn remove_from_outbox(&self, id: i64) -> rusqlite::Result<()>;
+
+    /// Counts a failed upload of a record, keeping the error for troubleshooting.
+    fn record_outbox_failure(&self, id: i64, error: &str) -> rusqlite::Result<()>;
+}
+
+impl Outbox for Connection {
+    fn enqueue(&self, path: &str, body: &str) -> rusqlite::Result<()> {
+        self.execute(QUERY_INSERT_OUTBOX, params!(path, body, now_epoch()))?;
+
+        Ok(())
+    }
+
+    fn get_outbox(&self, limit: usize) -> rusqlite::Result<Vec<OutboxEntry>> {
+        let mut stmt = self.prepare(QUERY_SELECT_OUTBOX)?;
+        let entry_rows = stmt.query_map(params!(limit), |row| {
+            Ok(OutboxEntry {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                body: row.get(2)?,
+                created_epoch: row.get(3)?,
+                attempts: row.get(4)?,
+                last_error: row.get(5)?,
+            })
+        })?;
+
+        entry_rows.collect()
+    }
+
+    fn count_outbox(&self) -> rusqlite::Result<u64> {
+        self.query_row(QUERY_COUNT_OUTBOX, (), |row| row.get(0))
+    }
+
+    fn remove_from_outbox(&self, id: i64) -> rusqlite::Result<()> {
+        self.execute(QUERY_DELETE_OUTBOX, params!(id))?;
+
+        Ok(())
+    }
+
+    fn record_outbox_failure(&self, id: i64, error: &str) -> rusqlite::Result<()> {
+        self.execute(QUERY_UPDATE_OUTBOX_FAILED, params!(id, error))?;
 
         Ok(())
     }
 }
 
 pub trait GetObservations {
-    fn get_observations(&self, limit: usize) -> rusqlite::Result<Vec<Weather>>;
-    fn get_latest_observation(&self) -> Option<Weather>;
+    /// Returns the most recent observations, newest first. If `serial_number` is given, only
+    /// observations from that device are returned, including ones merged from it and its AIR or
+    /// SKY partner.
+    fn get_observations(
+        &self,
+        limit: usize,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Vec<Weather>>;
+    /// Returns the observations in the given range, in the range's order.
+    fn get_observations_in_range(&self, range: &ObservationRange)
+        -> rusqlite::Result<Vec<Weather>>;
+    /// Returns the newest observation, or `None` if none have been stored. If `serial_number` is
+    /// given, only observations from that device are considered.
+    fn get_latest_observation(
+        &self,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Option<Weather>>;
+}
+
+fn weather_from_row(row: &Row) -> rusqlite::Result<Weather> {
+    Ok(Weather {
+        time_epoch: row.get(1)?,
+        wind_lull: row.get(2)?,
+        wind_avg: row.get(3)?,
+        wind_gust: row.get(4)?,
+        wind_direction: row.get(5)?,
+        wind_sample_interval: row.get(6)?,
+        station_pressure: row.get(7)?,
+        air_temp: row.get(8)?,
+        relative_humidity: row.get(9)?,
+        illuminance: row.get(10)?,
+        uv_index: row.get(11)?,
+        solar_radiation: row.get(12)?,
+        rain_over_prev_minute: row.get(13)?,
+        precip_type: row.get::<_, f64>(14)?.into(),
+        lightning_avg_distance: row.get(15)?,
+        lightning_strike_count: row.get(16)?,
+        battery_voltage: row.get(17)?,
+        report_interval: row.get(18)?,
+        serial_number: row.get::<_, Option<String>>(19)?.unwrap_or_default(),
+        hub_sn: row.get::<_, Option<String>>(20)?.unwrap_or_default(),
+    })
 }
 
 impl GetObservations for Connection {
-    fn get_observations(&self, limit: usize) -> rusqlite::Result<Vec<Weather>> {
-        let mut stmt = self.prepare("SELECT * FROM observation ORDER BY id DESC LIMIT ?1")?;
-        let weather_rows = stmt.query_map(params!(limit), |row| {
-            Ok(Weather {
-                time_epoch: row.get(1)?,
-                wind_lull: row.get(2)?,
-                wind_avg: row.get(3)?,
-                wind_gust: row.get(4)?,
-                wind_direction: row.get(5)?,
-                wind_sample_interval: row.get(6)?,
-                station_pressure: row.get(7)?,
-                air_temp: row.get(8)?,
-                relative_humidity: row.get(9)?,
-                illuminance: row.get(10)?,
-                uv_index: row.get(11)?,
-                solar_radiation: row.get(12)?,
-                rain_over_prev_minute: row.get(13)?,
-                precip_type: row.get::<_, f64>(14)?.into(),
-                lightning_avg_distance: row.get(15)?,
-                lightning_strike_count: row.get(16)?,
-                battery_voltage: row.get(17)?,
-                report_interval: row.get(18)?,
-            })
-        })?;
+    fn get_observations(
+        &self,
+        limit: usize,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Vec<Weather>> {
+        let mut stmt = self.prepare(QUERY_SELECT_OBSERVATIONS)?;
+        let weather_rows = stmt.query_map(params!(limit, serial_number), weather_from_row)?;
+
+        weather_rows.collect()
+    }
+
+    fn get_observations_in_range(
+        &self,
+        range: &ObservationRange,
+    ) -> rusqlite::Result<Vec<Weather>> {
+        let (from, to, limit, offset) = range.bounds();
+        let mut stmt = self.prepare(range.query())?;
+        let weather_rows = stmt.query_map(
+            params!(from, to, range.serial_number, limit, offset),
+            weather_from_row,
+        )?;
 
         weather_rows.collect()
     }
 
-    fn get_latest_observation(&self) -> Option<Weather> {
-        self.get_observations(1).ok()?.first().map(|w| *w)
+    fn get_latest_observation(
+        &self,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Option<Weather>> {
+        Ok(self.get_observations(1, serial_number)?.into_iter().next())
+    }
+}
+
+pub trait GetPressureTrend {
+    /// Returns the pressure trend over the `window` seconds before `end_epoch`, or `None` if
+    /// there aren't enough observations in that time. If `serial_number` is given, only
+    /// observations from that device are used.
+    fn get_pressure_trend(
+        &self,
+        end_epoch: u64,
+        window: u64,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Option<PressureTrend>>;
+}
+
+impl GetPressureTrend for Connection {
+    fn get_pressure_trend(
+        &self,
+        end_epoch: u64,
+        window: u64,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Option<PressureTrend>> {
+        let mut stmt = self.prepare(QUERY_SELECT_PRESSURE_SAMPLES)?;
+        let samples = stmt
+            .query_map(
+                params!(
+                    end_epoch.saturating_sub(window),
+                    end_epoch + 1,
+                    serial_number
+                ),
+                |row| {
+                    Ok(PressureSample {
+                        time_epoch: row.get(0)?,
+                        station_pressure: row.get(1)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(PressureTrend::from_samples(&samples, window))
+    }
+}
+
+fn rapid_wind_from_row(row: &Row) -> rusqlite::Result<RapidWind> {
+    Ok(RapidWind {
+        time_epoch: row.get(1)?,
+        wind_speed: row.get(2)?,
+        wind_direction: row.get(3)?,
+        serial_number: row.get(4)?,
+    })
+}
+
+pub trait GetRapidWind {
+    /// Returns the wind samples taken from `start_epoch` (inclusive) to `end_epoch` (exclusive),
+    /// oldest first.
+    fn get_rapid_wind(&self, start_epoch: u64, end_epoch: u64) -> rusqlite::Result<Vec<RapidWind>>;
+}
+
+impl GetRapidWind for Connection {
+    fn get_rapid_wind(&self, start_epoch: u64, end_epoch: u64) -> rusqlite::Result<Vec<RapidWind>> {
+        let mut stmt = self.prepare(QUERY_SELECT_RAPID_WIND_RANGE)?;
+        let sample_rows = stmt.query_map(params!(start_epoch, end_epoch), rapid_wind_from_row)?;
+
+        sample_rows.collect()
+    }
+}
+
+fn lightning_strike_from_row(row: &Row) -> rusqlite::Result<LightningStrike> {
+    Ok(LightningStrike {
+        time_epoch: row.get(1)?,
+        distance: row.get(2)?,
+        energy: row.get(3)?,
+        serial_number: row.get(4)?,
+    })
+}
+
+pub trait GetLightningStrikes {
+    /// Returns the strikes detected from `start_epoch` (inclusive) to `end_epoch` (exclusive),
+    /// oldest first.
+    fn get_lightning_strikes(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<LightningStrike>>;
+}
+
+impl GetLightningStrikes for Connection {
+    fn get_lightning_strikes(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<LightningStrike>> {
+        let mut stmt = self.prepare(QUERY_SELECT_LIGHTNING_STRIKE_RANGE)?;
+        let strike_rows =
+            stmt.query_map(params!(start_epoch, end_epoch), lightning_strike_from_row)?;
+
+        strike_rows.collect()
+    }
+}
+
+fn rain_start_from_row(row: &Row) -> rusqlite::Result<RainStart> {
+    Ok(RainStart {
+        time_epoch: row.get(1)?,
+        serial_number: row.get(2)?,
+    })
+}
+
+pub trait GetRainStarts {
+    /// Returns the rain onsets detected from `start_epoch` (inclusive) to `end_epoch` (exclusive),
+    /// oldest first.
+    fn get_rain_starts(&self, start_epoch: u64, end_epoch: u64)
+        -> rusqlite::Result<Vec<RainStart>>;
+}
+
+impl GetRainStarts for Connection {
+    fn get_rain_starts(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<RainStart>> {
+        let mut stmt = self.prepare(QUERY_SELECT_RAIN_START_RANGE)?;
+        let rain_start_rows =
+            stmt.query_map(params!(start_epoch, end_epoch), rain_start_from_row)?;
+
+        rain_start_rows.collect()
+    }
+}
+
+fn device_status_from_row(row: &Row) -> rusqlite::Result<DeviceStatus> {
+    Ok(DeviceStatus {
+        time_epoch: row.get(1)?,
+        serial_number: row.get(2)?,
+        hub_sn: row.get(3)?,
+        uptime: row.get(4)?,
+        voltage: row.get(5)?,
+        firmware_revision: row.get(6)?,
+        rssi: row.get(7)?,
+        hub_rssi: row.get(8)?,
+        sensor_status: row.get(9)?,
+        debug: row.get(10)?,
+    })
+}
+
+pub trait GetDeviceStatuses {
+    /// Returns the device status reports from `start_epoch` (inclusive) to `end_epoch`
+    /// (exclusive), oldest first.
+    fn get_device_statuses(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<DeviceStatus>>;
+}
+
+impl GetDeviceStatuses for Connection {
+    fn get_device_statuses(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<DeviceStatus>> {
+        let mut stmt = self.prepare(QUERY_SELECT_DEVICE_STATUS_RANGE)?;
+        let status_rows =
+            stmt.query_map(params!(start_epoch, end_epoch), device_status_from_row)?;
+
+        status_rows.collect()
+    }
+}
+
+fn hub_status_from_row(row: &Row) -> rusqlite::Result<HubStatus> {
+    Ok(HubStatus {
+        time_epoch: row.get(1)?,
+        serial_number: row.get(2)?,
+        firmware_revision: row.get(3)?,
+        uptime: row.get(4)?,
+        rssi: row.get(5)?,
+        reset_flags: row.get(6)?,
+        seq: row.get(7)?,
+        radio_version: row.get(8)?,
+        reboot_count: row.get(9)?,
+        bus_error_count: row.get(10)?,
+        radio_status: row.get(11)?,
+        radio_network_id: row.get(12)?,
+    })
+}
+
+pub trait GetHubStatuses {
+    /// Returns the hub status reports from `start_epoch` (inclusive) to `end_epoch` (exclusive),
+    /// oldest first.
+    fn get_hub_statuses(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<HubStatus>>;
+}
+
+impl GetHubStatuses for Connection {
+    fn get_hub_statuses(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> rusqlite::Result<Vec<HubStatus>> {
+        let mut stmt = self.prepare(QUERY_SELECT_HUB_STATUS_RANGE)?;
+        let status_rows = stmt.query_map(params!(start_epoch, end_epoch), hub_status_from_row)?;
+
+        status_rows.collect()
+    }
+}
+
+/// The id of the newest row seen in each table of events, or 0 if none has been.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowIds {
+    pub observation: i64,
+    pub rapid_wind: i64,
+    pub lightning_strike: i64,
+    pub rain_start: i64,
+    pub device_status: i64,
+    pub hub_status: i64,
+}
+
+/// The rows of each table of events stored after some `RowIds`, in the order they were stored.
+#[derive(Debug, Default)]
+pub struct NewRows {
+    pub observations: Vec<Weather>,
+    pub rapid_wind: Vec<RapidWind>,
+    pub lightning_strikes: Vec<LightningStrike>,
+    pub rain_starts: Vec<RainStart>,
+    pub device_statuses: Vec<DeviceStatus>,
+    pub hub_statuses: Vec<HubStatus>,
+}
+
+pub trait GetNewRows {
+    /// Returns the id of the newest row in each table of events.
+    fn get_latest_row_ids(&self) -> rusqlite::Result<RowIds>;
+    /**
+    Returns the rows stored after `ids`, and moves `ids` past them.
+
+    Rows are matched by id rather than time, so ones stored late, with a time older than rows
+    already seen, are still returned. Ids are never reused, even once pruned, so no row is missed.
+    */
+    fn get_new_rows(&self, ids: &mut RowIds) -> rusqlite::Result<NewRows>;
+}
+
+/// The rows `query` selects after `id`, which moves to the last one's.
+fn rows_after<T>(
+    conn: &Connection,
+    query: &str,
+    id: &mut i64,
+    from_row: fn(&Row) -> rusqlite::Result<T>,
+) -> rusqlite::Result<Vec<T>> {
+    let mut stmt = conn.prepare(query)?;
+    let rows = stmt
+        .query_map(params!(*id), |row| {
+            Ok((row.get::<_, i64>(0)?, from_row(row)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if let Some((last, _)) = rows.last() {
+        *id = *last;
+    }
+
+    Ok(rows.into_iter().map(|(_, row)| row).collect())
+}
+
+impl GetNewRows for Connection {
+    fn get_latest_row_ids(&self) -> rusqlite::Result<RowIds> {
+        self.query_row(QUERY_SELECT_LATEST_IDS, [], |row| {
+            Ok(RowIds {
+                observation: row.get(0)?,
+                rapid_wind: row.get(1)?,
+                lightning_strike: row.get(2)?,
+                rain_start: row.get(3)?,
+                device_status: row.get(4)?,
+                hub_status: row.get(5)?,
+            })
+        })
+    }
+
+    fn get_new_rows(&self, ids: &mut RowIds) -> rusqlite::Result<NewRows> {
+        Ok(NewRows {
+            observations: rows_after(
+                self,
+                QUERY_SELECT_OBSERVATIONS_AFTER_ID,
+                &mut ids.observation,
+                weather_from_row,
+            )?,
+            rapid_wind: rows_after(
+                self,
+                QUERY_SELECT_RAPID_WIND_AFTER_ID,
+                &mut ids.rapid_wind,
+                rapid_wind_from_row,
+            )?,
+            lightning_strikes: rows_after(
+                self,
+                QUERY_SELECT_LIGHTNING_STRIKE_AFTER_ID,
+                &mut ids.lightning_strike,
+                lightning_strike_from_row,
+            )?,
+            rain_starts: rows_after(
+                self,
+                QUERY_SELECT_RAIN_START_AFTER_ID,
+                &mut ids.rain_start,
+                rain_start_from_row,
+            )?,
+            device_statuses: rows_after(
+                self,
+                QUERY_SELECT_DEVICE_STATUS_AFTER_ID,
+                &mut ids.device_status,
+                device_status_from_row,
+            )?,
+            hub_statuses: rows_after(
+                self,
+                QUERY_SELECT_HUB_STATUS_AFTER_ID,
+                &mut ids.hub_status,
+                hub_status_from_row,
+            )?,
+        })
+    }
+}
+
+pub trait GetRollups {
+    /// Returns the rollups for periods starting from `start_epoch` (inclusive) to `end_epoch`
+    /// (exclusive), oldest first. If `serial_number` is given, only rollups for that device are
+    /// returned.
+    fn get_rollups(
+        &self,
+        period: RollupPeriod,
+        start_epoch: u64,
+        end_epoch: u64,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Vec<Rollup>>;
+}
+
+impl GetRollups for Connection {
+    fn get_rollups(
+        &self,
+        period: RollupPeriod,
+        start_epoch: u64,
+        end_epoch: u64,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<Vec<Rollup>> {
+        let mut stmt = self.prepare(period.select_query())?;
+        let rollup_rows =
+            stmt.query_map(params!(start_epoch, end_epoch, serial_number), |row| {
+                Ok(Rollup {
+                    period_start: row.get(0)?,
+                    serial_number: row.get(1)?,
+                    sample_count: row.get(2)?,
+                    air_temp_min: row.get(3)?,
+                    air_temp_max: row.get(4)?,
+                    air_temp_mean: row.get(5)?,
+                    relative_humidity_min: row.get(6)?,
+                    relative_humidity_max: row.get(7)?,
+                    relative_humidity_mean: row.get(8)?,
+                    station_pressure_min: row.get(9)?,
+                    station_pressure_max: row.get(10)?,
+                    station_pressure_mean: row.get(11)?,
+                    wind_avg_mean: row.get(12)?,
+                    wind_gust_max: row.get(13)?,
+                    wind_gust_direction: row.get(14)?,
+                    rain_total: row.get(15)?,
+                    uv_index_max: row.get(16)?,
+                    solar_energy: row.get(17)?,
+                    lightning_strike_count: row.get(18)?,
+                })
+            })?;
+
+        rollup_rows.collect()
+    }
+}
+
+pub trait GetRainTotals {
+    /**
+    Returns the rain accumulated over each of the given periods. If `serial_number` is given, only
+    rain measured by that device is counted, and otherwise only rain measured by the station that
+    reported the latest observation.
+
+    Where observations have already been pruned, the daily rollups fill in the missing days of
+    today, yesterday, this month and this year. Those are counted from midnight, so with a later
+    rain day start the first partial day of a period that old is approximate, and the day the
+    oldest remaining observation falls on only counts what's left of it.
+    */
+    fn get_rain_totals(
+        &self,
+        periods: &RainPeriods,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<RainTotals>;
+}
+
+fn rain_sums(
+    conn: &Connection,
+    periods: &RainPeriods,
+    serial_number: Option<&str>,
+) -> rusqlite::Result<RainSums> {
+    conn.query_row(
+        QUERY_SELECT_RAIN_TOTALS,
+        params!(
+            periods.last_hour,
+            periods.last_24h,
+            periods.today,
+            periods.yesterday,
+            periods.month,
+            periods.year,
+            periods.end,
+            periods.rate_start(),
+            serial_number
+        ),
+        |row| {
+            Ok(RainSums {
+                last_hour: row.get(0)?,
+                last_24h: row.get(1)?,
+                today: row.get(2)?,
+                yesterday: row.get(3)?,
+                month: row.get(4)?,
+                year: row.get(5)?,
+                latest_minute: row.get(6)?,
+            })
+        },
+    )
+}
+
+impl GetRainTotals for Connection {
+    fn get_rain_totals(
+        &self,
+        periods: &RainPeriods,
+        serial_number: Option<&str>,
+    ) -> rusqlite::Result<RainTotals> {
+        // Without a serial number, only the station that reported the latest observation is
+        // counted, as `QUERY_SELECT_RAIN_TOTALS` does, so the rollups of others aren't added in.
+        let latest = match serial_number {
+            Some(_) => None,
+            None => self.get_latest_observation(None)?,
+        };
+        let serial_number = serial_number.or(latest
+            .as_ref()
+            .map(|weather| weather.serial_number.as_str())
+            .filter(|serial_number| !serial_number.is_empty()));
+
+        let oldest: Option<u64> = self.query_row(
+            QUERY_SELECT_OLDEST_OBSERVATION_TIME,
+            params!(serial_number),
+            |row| row.get(0),
+        )?;
+
+        let mut totals: RainTotals = rain_sums(self, periods, serial_number)?.into();
+
+        // Days before the one the oldest observation falls on have been pruned, if there's
+        // anything there at all.
+        let pruned_before = match oldest {
+            Some(oldest) if oldest <= periods.earliest() => return Ok(totals),
+            Some(oldest) => RollupPeriod::Daily.period_start(oldest),
+            None => periods.end,
+        };
+
+        let rollup_rain = |start: u64, end: u64| -> rusqlite::Result<f32> {
+            let end = end.min(pruned_before);
+
+            if start >= end {
+                return Ok(0.0);
+            }
+
+            self.query_row(
+                QUERY_SELECT_ROLLUP_DAILY_RAIN,
+                params!(RollupPeriod::Daily.period_start(start), end, serial_number),
+                |row| row.get(0),
+            )
+        };
+
+        // The last hour and day don't start at midnight, so they can't be made up from whole days
+        // and only count the observations still stored.
+        totals.today += rollup_rain(periods.today, periods.end)?;
+        totals.yesterday += rollup_rain(periods.yesterday, periods.today)?;
+        totals.month += rollup_rain(periods.month, periods.end)?;
+        totals.year += rollup_rain(periods.year, periods.end)?;
+
+        Ok(totals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::weather::PrecipitationType;
+
+    fn memory() -> Connection {
+        connect(&DatabaseLocation::InMemory).unwrap()
+    }
+
+    fn weather(serial_number: &str, time_epoch: u64) -> Weather {
+        Weather {
+            time_epoch,
+            wind_lull: 0.0,
+            wind_avg: 0.0,
+            wind_gust: 0.0,
+            wind_direction: 0,
+            wind_sample_interval: 3,
+            station_pressure: 1000.0,
+            air_temp: 20.0,
+            relative_humidity: 50.0,
+            illuminance: 0,
+            uv_index: 0.0,
+            solar_radiation: 0,
+            rain_over_prev_minute: 0.0,
+            precip_type: PrecipitationType::None,
+            lightning_avg_distance: Some(0),
+            lightning_strike_count: 0,
+            battery_voltage: 2.6,
+            report_interval: 1,
+            serial_number: serial_number.to_string(),
+            hub_sn: "HB-1".to_string(),
+        }
+    }
+
+    /// A path for a database file that doesn't exist yet.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("tempestrs-{}-{}.db3", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn migrates_to_the_latest_version() {
+        let latest = core::queries::MIGRATIONS.last().unwrap().version;
+        let mut conn = memory();
+
+        assert_eq!(conn.schema_version().unwrap(), latest);
+        assert_eq!(conn.migrate().unwrap(), latest);
+    }
+
+    #[test]
+    fn concurrent_connections_migrate_the_same_database() {
+        let latest = core::queries::MIGRATIONS.last().unwrap().version;
+        let path = temp_path("concurrent-migrate");
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(4));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mut conn = Connection::open(&path).unwrap();
+                let barrier = barrier.clone();
+
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    conn.migrate()
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().unwrap(), latest);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn deduplicating_keeps_rows_without_a_serial_number() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        conn.execute(QUERY_CREATE_TABLE_SCHEMA_VERSION, ()).unwrap();
+
+        for migration in pending_migrations(0).take_while(|migration| migration.version < 8) {
+            for statement in migration.statements {
+                conn.execute(statement, ()).unwrap();
+            }
+
+            conn.execute(
+                QUERY_INSERT_SCHEMA_VERSION,
+                params!(migration.version, migration.description, 0),
+            )
+            .unwrap();
+        }
+
+        for serial_number in [None, None, Some("ST-1"), Some("ST-1"), Some("ST-2")] {
+            conn.execute(
+                "INSERT INTO observation (time_epoch, serial_number) VALUES (1000, ?1)",
+                params!(serial_number),
+            )
+            .unwrap();
+        }
+
+        conn.migrate().unwrap();
+
+        let count = |condition: &str| -> usize {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM observation WHERE {}", condition),
+                (),
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(count("serial_number IS NULL"), 2);
+        assert_eq!(count("serial_number = 'ST-1'"), 1);
+        assert_eq!(count("serial_number = 'ST-2'"), 1);
+    }
+
+    #[test]
+    fn ignores_duplicate_observations() {
+        let conn = memory();
+
+        assert!(conn.insert_observation(&weather("ST-1", 1000)).unwrap());
+        assert!(!conn.insert_observation(&weather("ST-1", 1000)).unwrap());
+        assert!(conn.insert_observation(&weather("ST-2", 1000)).unwrap());
+        assert_eq!(conn.get_observations(10, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn has_no_latest_observation_when_empty() {
+        let conn = memory();
+
+        assert!(conn.get_latest_observation(None).unwrap().is_none());
+
+        conn.insert_observation(&weather("ST-1", 1000)).unwrap();
+        conn.insert_observation(&weather("ST-1", 2000)).unwrap();
+
+        let latest = conn.get_latest_observation(None).unwrap().unwrap();
+        assert_eq!(latest.time_epoch, 2000);
+        assert!(conn.get_latest_observation(Some("ST-2")).unwrap().is_none());
+    }
+
+    #[test]
+    fn fills_in_pruned_days_from_rollups_but_not_the_last_hours() {
+        let conn = memory();
+        let today = RollupPeriod::Daily.period_start(1_718_452_800);
+        let yesterday = RollupPeriod::Daily.period_start(today - 1);
+        let now = today + 12 * 3600;
+
+        for time_epoch in [yesterday + 2 * 3600, today + 8 * 3600, now - 1800] {
+            conn.insert_observation(&Weather {
+                rain_over_prev_minute: 1.0,
+                ..weather("ST-1", time_epoch)
+            })
+            .unwrap();
+        }
+
+        // Keep only the last six hours of observations.
+        conn.execute(
+            "DELETE FROM observation WHERE time_epoch < ?1",
+            [now - 6 * 3600],
+        )
+        .unwrap();
+
+        let periods = RainPeriods {
+            last_hour: now - 3600,
+            last_24h: now - 24 * 3600,
+            today,
+            yesterday,
+            month: yesterday,
+            year: yesterday,
+            end: now + 1,
+        };
+        let totals = conn.get_rain_totals(&periods, None).unwrap();
+
+        assert_eq!(totals.last_hour, 1.0);
+        assert_eq!(totals.last_24h, 2.0);
+        assert_eq!(totals.today, 2.0);
+        assert_eq!(totals.yesterday, 1.0);
+        assert_eq!(totals.month, 3.0);
+        assert_eq!(totals.year, 3.0);
+    }
+
+    #[test]
+    fn sums_rain_of_the_latest_station_without_a_serial() {
+        let conn = memory();
+        let today = RollupPeriod::Daily.period_start(1_718_452_800);
+        let now = today + 12 * 3600;
+
+        for (serial_number, time_epoch) in [("ST-1", now - 120), ("ST-2", now - 60)] {
+            conn.insert_observation(&Weather {
+                rain_over_prev_minute: 1.0,
+                ..weather(serial_number, time_epoch)
+            })
+            .unwrap();
+        }
+
+        let periods = RainPeriods {
+            last_hour: now - 3600,
+            last_24h: now - 24 * 3600,
+            today,
+            yesterday: today - 24 * 3600,
+            month: today,
+            year: today,
+            end: now + 1,
+        };
+
+        assert_eq!(rain_sums(&conn, &periods, None).unwrap().today, 1.0);
+        assert_eq!(conn.get_rain_totals(&periods, None).unwrap().today, 1.0);
+        assert_eq!(
+            conn.get_rain_totals(&periods, Some("ST-1")).unwrap().today,
+            1.0
+        );
+    }
+
+    #[test]
+    fn filters_merged_observations_by_either_serial() {
+        let conn = memory();
+
+        conn.insert_observation(&weather("AR-1/SK-1", 1000))
+            .unwrap();
+        conn.insert_observation(&weather("ST-1", 1000)).unwrap();
+
+        for serial in ["AR-1", "SK-1", "AR-1/SK-1"] {
+            let observations = conn.get_observations(10, Some(serial)).unwrap();
+
+            assert_eq!(observations.len(), 1, "{}", serial);
+            assert_eq!(observations[0].serial_number, "AR-1/SK-1");
+        }
+
+        assert!(conn.get_observations(10, Some("AR-")).unwrap().is_empty());
+        assert_eq!(conn.get_observations(10, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn returns_rows_stored_late_once() {
+        let conn = memory();
+        conn.insert_observation(&weather("ST-1", 2000)).unwrap();
+        let mut ids = conn.get_latest_row_ids().unwrap();
+
+        conn.insert_observation(&weather("ST-1", 1000)).unwrap();
+        conn.insert_rain_start(&RainStart {
+            time_epoch: 900,
+            serial_number: "ST-1".to_string(),
+        })
+        .unwrap();
+
+        let rows = conn.get_new_rows(&mut ids).unwrap();
+        assert_eq!(rows.observations.len(), 1);
+        assert_eq!(rows.observations[0].time_epoch, 1000);
+        assert_eq!(rows.rain_starts.len(), 1);
+
+        let rows = conn.get_new_rows(&mut ids).unwrap();
+        assert!(rows.observations.is_empty());
+        assert!(rows.rain_starts.is_empty());
+    }
+
+    #[test]
+    fn returns_rows_stored_after_the_newest_were_pruned() {
+        let conn = memory();
+        conn.insert_observation(&weather("ST-1", 1000)).unwrap();
+        conn.insert_observation(&weather("ST-1", 1060)).unwrap();
+        let mut ids = conn.get_latest_row_ids().unwrap();
+
+        conn.execute("DELETE FROM observation", []).unwrap();
+        conn.insert_observation(&weather("ST-1", 1120)).unwrap();
+        conn.insert_observation(&weather("ST-1", 1180)).unwrap();
+
+        let rows = conn.get_new_rows(&mut ids).unwrap();
+        assert_eq!(rows.observations.len(), 2);
+        assert_eq!(rows.observations[0].time_epoch, 1120);
+    }
+
+    #[test]
+    fn keeps_rows_and_ids_when_ids_stop_being_reused() {
+        use core::queries::{
+            QUERY_ALTER_OBSERVATION_ADD_HUB_SN, QUERY_ALTER_OBSERVATION_ADD_SERIAL_NUMBER,
+            QUERY_CREATE_TABLE_OBSERVATION, QUERY_CREATE_UNIQUE_INDEX_OBSERVATION,
+        };
+
+        let mut conn = memory();
+        conn.execute("DELETE FROM schema_version WHERE version >= 10", [])
+            .unwrap();
+        conn.execute("DROP TABLE observation", []).unwrap();
+        for statement in [
+            QUERY_CREATE_TABLE_OBSERVATION,
+            QUERY_ALTER_OBSERVATION_ADD_SERIAL_NUMBER,
+            QUERY_ALTER_OBSERVATION_ADD_HUB_SN,
+            QUERY_CREATE_UNIQUE_INDEX_OBSERVATION,
+        ] {
+            conn.execute(statement, []).unwrap();
+        }
+        conn.insert_observation(&weather("ST-1", 1000)).unwrap();
+        conn.insert_observation(&weather("ST-1", 1060)).unwrap();
+
+        conn.migrate().unwrap();
+
+        assert_eq!(conn.get_latest_row_ids().unwrap().observation, 2);
+        assert!(!conn.insert_observation(&weather("ST-1", 1000)).unwrap());
+
+        conn.execute("DELETE FROM observation WHERE time_epoch = 1060", [])
+            .unwrap();
+        conn.insert_observation(&weather("ST-1", 1120)).unwrap();
+
+        assert!(conn.get_latest_row_ids().unwrap().observation > 2);
     }
 }
@@ -0,0 +1,66 @@
+use std::{convert::Infallible, env, path::PathBuf, str::FromStr};
+
+use crate::{config::Config, Error};
+
+/// Where a database lives. Parsed from a string, which may be `:memory:` for a throwaway
+/// in-memory database, a path to a file (anything containing a path separator or ending in
+/// `.db3`), or a bare name for a database in the data directory, so that several can be kept side
+/// by side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseLocation {
+    InMemory,
+    Path(PathBuf),
+    Named(String),
+}
+
+impl FromStr for DatabaseLocation {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s == ":memory:" {
+            DatabaseLocation::InMemory
+        } else if s.contains(std::path::MAIN_SEPARATOR) || s.contains('/') || s.ends_with(".db3") {
+            DatabaseLocation::Path(PathBuf::from(s))
+        } else {
+            DatabaseLocation::Named(s.to_string())
+        })
+    }
+}
+
+impl DatabaseLocation {
+    /// Picks the database to use: the given flag, then `$TEMPESTRS_DB`, then the config file, then
+    /// `~/.tempestrs/weather.db3` if it exists from older versions, and finally the database named
+    /// `weather` in the data directory.
+    pub fn resolve(flag: Option<&str>, config: &Config) -> DatabaseLocation {
+        let configured = flag
+            .map(str::to_string)
+            .or_else(|| env::var("TEMPESTRS_DB").ok())
+            .or_else(|| config.database.clone());
+
+        if let Some(location) = configured {
+            return location.parse().unwrap();
+        }
+
+        if let Some(legacy) = dirs::home_dir().map(|dir| dir.join(".tempestrs").join("weather.db3"))
+        {
+            if legacy.exists() {
+                return DatabaseLocation::Path(legacy);
+            }
+        }
+
+        DatabaseLocation::Named("weather".to_string())
+    }
+
+    /// The file backing the database, or `None` for an in-memory one. Named databases live in
+    /// `tempestrs` under the data directory (`$XDG_DATA_HOME` or `~/.local/share` on Linux).
+    pub fn path(&self) -> Result<Option<PathBuf>, Error> {
+        match self {
+            DatabaseLocation::InMemory => Ok(None),
+            DatabaseLocation::Path(path) => Ok(Some(path.clone())),
+            DatabaseLocation::Named(name) => {
+                let dir = dirs::data_dir().ok_or(Error::NoDataDir)?;
+                Ok(Some(dir.join("tempestrs").join(format!("{}.db3", name))))
+            }
+        }
+    }
+}
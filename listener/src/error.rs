@@ -0,0 +1,56 @@
+use std::fmt::Display;
+use std::io;
+
+/// Everything that can go wrong in the listener, wrapping the errors of the crates it uses.
+#[derive(Debug)]
+pub enum Error {
+    /// A datagram that couldn't be read as a supported packet.
+    Packet(core::error::Error),
+    Database(db::Error),
+    /// Receiving from a socket failed.
+    Network(io::Error),
+    /// Reading or writing a recording failed.
+    Recording(io::Error),
+    /// A setting on the command line or in the environment is invalid.
+    Config(String),
+}
+
+impl Error {
+    /// A short name for the kind of error, for logs and counters.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Packet(core::error::Error::InvalidPacket(_)) => "invalid_packet",
+            Error::Packet(core::error::Error::UnknownPacketType(_)) => "unknown_packet_type",
+            Error::Database(_) => "database",
+            Error::Network(_) => "network",
+            Error::Recording(_) => "recording",
+            Error::Config(_) => "config",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Packet(error) => write!(f, "{}", error),
+            Error::Database(error) => write!(f, "database error: {}", error),
+            Error::Network(error) => write!(f, "network error: {}", error),
+            Error::Recording(error) => write!(f, "recording error: {}", error),
+            Error::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<core::error::Error> for Error {
+    fn from(error: core::error::Error) -> Self {
+        Error::Packet(error)
+    }
+}
+
+impl From<db::Error> for Error {
+    fn from(error: db::Error) -> Self {
+        Error::Database(error)
+    }
+}
@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::Error;
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        <LogFormat as ValueEnum>::from_str(value, true)
+            .map_err(|_| format!("unknown log format: {}", value))
+    }
+}
+
+/**
+Starts logging to stdout.
+
+`filter` picks what's logged, in `RUST_LOG` syntax: a default level, optionally followed by
+overrides for particular targets. Every packet is logged at debug level under the target
+`packet::<type>`, with its full contents at trace level, so "info,packet::evt_strike=debug" logs
+lightning strikes without the flood of wind samples, and "debug,packet::rapid_wind=info" logs
+every packet but those.
+*/
+pub fn init(filter: &str, format: LogFormat) -> Result<(), Error> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|error| Error::Config(format!("invalid log filter {}: {}", filter, error)))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+
+    Ok(())
+}
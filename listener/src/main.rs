@@ -1,42 +1,267 @@
-use core::packet::Packet;
-use core::weather::IntoWeather;
-use db;
-use db::InsertObservation;
-use serde_json;
-use std::net::UdpSocket;
+use clap::Parser;
+use core::retention::RetentionPolicy;
+use core::weather::WeatherMerger;
+use db::config::Config;
+use db::{DatabaseLocation, Outbox};
+use error::Error;
+use logging::LogFormat;
+use pipeline::{Pipeline, SerialFilter};
+use record::{Datagram, Recorder};
+use std::env;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use upload::Uploader;
 
-fn main() {
-    let socket = UdpSocket::bind("0.0.0.0:50222").expect("unable to bind to port 50222");
-    let conn = db::connect().unwrap();
+mod error;
+mod logging;
+mod pipeline;
+mod record;
+mod upload;
 
-    loop {
-        let mut buf = [0u8; 64000];
-        let result = socket.recv(&mut buf);
+/// How often periodic work, like retrying uploads, is done while no packets are arriving.
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Parser)]
+#[command(about = "Record weather broadcasts from Tempest hubs on the local network")]
+struct Args {
+    /// Database to write: a path, ":memory:", or the name of a database in the data directory.
+    /// Defaults to $TEMPESTRS_DB, then the config file, then "weather"
+    #[arg(long)]
+    db: Option<String>,
+    /// Address to listen on, as an IPv4 or IPv6 address with an optional port, e.g.
+    /// "0.0.0.0", "192.168.10.255" or "[::]:50222". Repeat to listen on several at once. Hubs
+    /// broadcast, and broadcasts only arrive at "0.0.0.0", which receives them from every
+    /// interface, or at the broadcast address they were sent to, e.g. a subnet's. On Linux and
+    /// BSD an interface's own address, like "192.168.10.2", receives none. Defaults to the config
+    /// file's listener.bind setting, then "0.0.0.0". On systems where IPv6 sockets also accept
+    /// IPv4, "[::]" can't be combined with "0.0.0.0" on the same port
+    #[arg(long)]
+    bind: Vec<String>,
+    /// Port for addresses given without one. Defaults to the config file's listener.port
+    /// setting, then 50222
+    #[arg(long)]
+    port: Option<u16>,
+    /// Serial number of a device or hub to accept packets from. Repeat to accept several;
+    /// packets from anything else are ignored. Defaults to the config file's listener.serials
+    /// setting, which accepts everything if empty
+    #[arg(long = "serial")]
+    serials: Vec<String>,
+    /// URL of the worker to forward observations to, e.g. "https://weather.example.com".
+    /// Observations are queued in the database until the worker accepts them. Defaults to the
+    /// config file's listener.upload_url setting
+    #[arg(long)]
+    upload_url: Option<String>,
+    /// Append every datagram received to this file, one JSON object per line with the time it
+    /// was received, for --replay
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Instead of listening, feed the datagrams recorded in this file through the usual
+    /// processing, then exit. Old data isn't pruned while replaying
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// How many times faster than real time to replay
+    #[arg(long, default_value_t = 1.0, requires = "replay", value_parser = parse_speed)]
+    speed: f64,
+    /// Replay as fast as possible instead of at the recorded pace
+    #[arg(long, requires = "replay", conflicts_with = "speed")]
+    fast: bool,
+    /// What to log, as a level optionally followed by overrides for particular targets, e.g.
+    /// "info,packet::evt_strike=debug". Each type of packet is logged at debug level under
+    /// "packet::<type>", with its contents at trace level. Defaults to $TEMPESTRS_LOG, then the
+    /// config file's listener.log setting, then "info"
+    #[arg(long)]
+    log: Option<String>,
+    /// How to write log lines. Defaults to the config file's listener.log_format setting, then
+    /// text
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("expected a positive number, got {}", value)),
+    }
+}
 
-        match result {
+/// Receives datagrams on `socket`, which is bound to `address`, until the program exits, sending
+/// each to `sender`.
+fn receive(socket: UdpSocket, address: SocketAddr, sender: Sender<Datagram>) {
+    let mut buf = [0u8; 64000];
+
+    loop {
+        match socket.recv(&mut buf) {
             Ok(num_bytes) => {
-                let payload = &buf[0..num_bytes];
-
-                match serde_json::from_slice::<Packet>(payload) {
-                    Ok(packet) => {
-                        println!("PACKET: {}", serde_json::to_string_pretty(&packet).unwrap());
-
-                        if let Some(weather) = packet.into_weather() {
-                            match conn.insert_observation(weather) {
-                                Err(error) => println!("DB ERROR: {:?}", error),
-                                _ => (),
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        println!("Unable to deserialize: {}", err);
-                    }
+                if sender
+                    .send(Datagram::received(address, &buf[0..num_bytes]))
+                    .is_err()
+                {
+                    return;
                 }
             }
+            Err(error) => {
+                let error = Error::Network(error);
+
+                warn!(%address, kind = error.kind(), %error, "unable to receive datagram");
+            }
+        }
+    }
+}
+
+fn run(args: Args, config: Config) -> Result<(), Error> {
+    let conn = db::connect(&DatabaseLocation::resolve(args.db.as_deref(), &config))?;
+
+    let listener_config = config.listener.unwrap_or_default();
+    let addresses = listener_config
+        .bind_addresses(&args.bind, args.port)
+        .map_err(Error::Config)?;
+    let serials = listener_config.accepted_serials(args.serials);
+
+    let replaying = args.replay.is_some();
+
+    // Pruning deletes data, so it only happens when a policy has been chosen. Replayed data is
+    // often old, and shouldn't be pruned as soon as it's stored.
+    let retention = match env::var("TEMPESTRS_RETENTION") {
+        Ok(policy) => Some(("TEMPESTRS_RETENTION", policy)),
+        Err(_) => listener_config
+            .retention
+            .map(|policy| ("listener.retention", policy)),
+    };
+    let retention = match retention {
+        _ if replaying => None,
+        Some((source, policy)) => Some(
+            policy
+                .parse::<RetentionPolicy>()
+                .map_err(|error| Error::Config(format!("invalid {}: {}", source, error)))?,
+        ),
+        None => {
+            info!("not pruning old data, since no retention policy is set");
+            None
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+
+    // The replay's result, which is only known once it's sent everything and hung up.
+    let mut replay = None;
+
+    if let Some(path) = args.replay {
+        let speed = (!args.fast).then_some(args.speed);
+
+        info!(path = %path.display(), "replaying recording");
+        replay = Some(thread::spawn(move || {
+            record::replay(&path, speed, sender).map_err(|error| (path, error))
+        }));
+    } else {
+        for address in addresses {
+            let socket = UdpSocket::bind(address).map_err(Error::Network)?;
+
+            info!(%address, "listening");
+
+            let sender = sender.clone();
+            thread::spawn(move || receive(socket, address, sender));
+        }
+    }
+
+    let recorder = args
+        .record
+        .as_deref()
+        .map(Recorder::open)
+        .transpose()
+        .map_err(Error::Recording)?;
+    let uploader = args
+        .upload_url
+        .or(listener_config.upload_url)
+        .map(|url| Uploader::new(&url));
+
+    if uploader.is_some() {
+        let count = conn.count_outbox().map_err(db::Error::from)?;
+
+        if count > 0 {
+            info!(queued = count, "observations are waiting to be uploaded");
+        }
+    }
 
-            Err(err) => {
-                println!("Network error: {}", err)
+    let mut pipeline = Pipeline::new(
+        conn,
+        SerialFilter::new(serials),
+        WeatherMerger::with_pairs(listener_config.pairs),
+        uploader,
+        recorder,
+        retention,
+    );
+
+    loop {
+        pipeline.tick();
+
+        match receiver.recv_timeout(TICK_INTERVAL) {
+            Ok(datagram) => pipeline.handle(&datagram),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                pipeline.counters().log();
+
+                if let Some(replay) = replay {
+                    let result = replay
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+
+                    if let Err((path, error)) = result {
+                        let error = Error::Recording(error);
+
+                        error!(path = %path.display(), kind = error.kind(), %error, "unable to replay");
+                        return Err(error);
+                    }
+
+                    info!("replay finished");
+                }
+
+                return Ok(());
             }
         }
     }
 }
+
+fn main() {
+    let args = Args::parse();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Unable to load config: {}", error);
+            process::exit(1);
+        }
+    };
+
+    let listener_config = config.listener.clone().unwrap_or_default();
+    let filter = args
+        .log
+        .clone()
+        .or_else(|| env::var("TEMPESTRS_LOG").ok())
+        .or(listener_config.log)
+        .unwrap_or_else(|| "info".to_string());
+    let format = match args.log_format {
+        Some(format) => format,
+        None => match listener_config.log_format.as_deref().map(str::parse) {
+            Some(Ok(format)) => format,
+            Some(Err(error)) => {
+                eprintln!("Invalid log format in config: {}", error);
+                process::exit(1);
+            }
+            None => LogFormat::default(),
+        },
+    };
+
+    if let Err(error) = logging::init(&filter, format) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+
+    if let Err(error) = run(args, config) {
+        error!(kind = error.kind(), %error, "listener stopped");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,413 @@
+use core::event::{IntoLightningStrike, IntoRainStart};
+use core::packet::Packet;
+use core::retention::RetentionPolicy;
+use core::status::{IntoDeviceStatus, IntoHubStatus};
+use core::util::now_epoch;
+use core::weather::{IntoPartialWeather, IntoRapidWind, IntoWeather, WeatherMerger};
+use db::{
+    Connection, InsertDeviceStatus, InsertHubStatus, InsertLightningStrike, InsertObservation,
+    InsertRainStart, InsertRapidWind, Prune,
+};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::error::Error;
+use crate::record::{Datagram, Recorder};
+use crate::upload::Uploader;
+
+/// How often old data is pruned according to the retention policy.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often the counters are logged.
+const COUNTERS_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Which devices and hubs packets are accepted from.
+pub struct SerialFilter {
+    allowed: HashSet<String>,
+    /// Serial numbers already reported as ignored, so each is only reported once.
+    ignored: HashSet<String>,
+}
+
+impl SerialFilter {
+    pub fn new(serials: Vec<String>) -> Self {
+        Self {
+            allowed: serials.into_iter().collect(),
+            ignored: HashSet::new(),
+        }
+    }
+
+    /// Whether to accept a packet: everything is accepted when no serials are listed, and
+    /// otherwise a packet is accepted if its device or hub is listed.
+    fn accepts(&mut self, packet: &Packet) -> bool {
+        if self.allowed.is_empty() {
+            return true;
+        }
+
+        let serials = [packet.serial_number(), packet.hub_sn()];
+
+        if serials
+            .iter()
+            .flatten()
+            .any(|serial| self.allowed.contains(*serial))
+        {
+            return true;
+        }
+
+        if let Some(serial) = packet.serial_number() {
+            if self.ignored.insert(serial.to_string()) {
+                info!(
+                    serial_number = serial,
+                    "ignoring packets from a serial that isn't allowed"
+                );
+            }
+        }
+
+        false
+    }
+}
+
+/// What's happened to the datagrams received since the listener started.
+#[derive(Debug, Default)]
+pub struct Counters {
+    pub datagrams: u64,
+    /// Packets accepted from allowed devices and hubs. Failures to store what they carry are
+    /// counted in `db_errors`.
+    pub packets: u64,
+    /// Packets from devices and hubs that aren't allowed.
+    pub filtered: u64,
+    /// Datagrams that aren't valid packets.
+    pub parse_failures: u64,
+    /// Valid packets of unsupported types.
+    pub unknown_packets: u64,
+    /// Failed database operations, including ones for packets that were otherwise stored.
+    pub db_errors: u64,
+}
+
+impl Counters {
+    pub fn log(&self) {
+        info!(
+            datagrams = self.datagrams,
+            packets = self.packets,
+            filtered = self.filtered,
+            parse_failures = self.parse_failures,
+            unknown_packets = self.unknown_packets,
+            db_errors = self.db_errors,
+            "counters"
+        );
+    }
+}
+
+/// Logs a packet at debug level, and its contents at trace level, under a target named for its
+/// type, so each type's verbosity can be set separately.
+fn log_packet(packet: &Packet, payload: &str) {
+    macro_rules! log_as {
+        ($target:literal) => {{
+            debug!(
+                target: $target,
+                serial_number = packet.serial_number(),
+                hub_sn = packet.hub_sn(),
+                "received packet"
+            );
+            trace!(target: $target, payload, "packet contents");
+        }};
+    }
+
+    match packet {
+        Packet::Observation { .. } => log_as!("packet::obs_st"),
+        Packet::AirObservation { .. } => log_as!("packet::obs_air"),
+        Packet::SkyObservation { .. } => log_as!("packet::obs_sky"),
+        Packet::RapidWind { .. } => log_as!("packet::rapid_wind"),
+        Packet::EventRainStart { .. } => log_as!("packet::evt_precip"),
+        Packet::EventLightningStrike { .. } => log_as!("packet::evt_strike"),
+        Packet::DeviceStatus { .. } => log_as!("packet::device_status"),
+        Packet::HubStatus { .. } => log_as!("packet::hub_status"),
+        Packet::Other => (),
+    }
+}
+
+/// Everything done with each datagram: recording it, parsing it, storing what it carries and
+/// queuing observations for upload, along with the periodic pruning and upload retries.
+pub struct Pipeline {
+    conn: Connection,
+    merger: WeatherMerger,
+    filter: SerialFilter,
+    uploader: Option<Uploader>,
+    recorder: Option<Recorder>,
+    /// The retention policy to prune with, if pruning at all.
+    retention: Option<RetentionPolicy>,
+    last_prune: Option<Instant>,
+    last_counters: Instant,
+    counters: Counters,
+}
+
+impl Pipeline {
+    pub fn new(
+        conn: Connection,
+        filter: SerialFilter,
+        merger: WeatherMerger,
+        uploader: Option<Uploader>,
+        recorder: Option<Recorder>,
+        retention: Option<RetentionPolicy>,
+    ) -> Self {
+        Self {
+            conn,
+            merger,
+            filter,
+            uploader,
+            recorder,
+            retention,
+            last_prune: None,
+            last_counters: Instant::now(),
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    /// Logs a failed database operation and counts it.
+    fn database_error(&mut self, operation: &str, error: impl Into<db::Error>) {
+        let error = Error::from(error.into());
+
+        self.counters.db_errors += 1;
+        error!(operation, kind = error.kind(), %error, "database operation failed");
+    }
+
+    /// Runs whatever periodic work is due.
+    pub fn tick(&mut self) {
+        if let Some(retention) = self.retention.as_ref() {
+            if self
+                .last_prune
+                .is_none_or(|time| time.elapsed() >= PRUNE_INTERVAL)
+            {
+                match self.conn.prune(retention, now_epoch(), false) {
+                    Ok(report) => {
+                        for entry in report.entries.iter().filter(|entry| entry.rows > 0) {
+                            info!(
+                                table = entry.table.name(),
+                                rows = entry.rows,
+                                cutoff_epoch = entry.cutoff_epoch,
+                                "pruned old rows"
+                            );
+                        }
+                    }
+                    Err(error) => self.database_error("prune", error),
+                }
+
+                self.last_prune = Some(Instant::now());
+            }
+        }
+
+        if let Some(uploader) = self.uploader.as_mut() {
+            if let Err(error) = uploader.flush(&self.conn) {
+                self.database_error("flush_outbox", error);
+            }
+        }
+
+        if self.last_counters.elapsed() >= COUNTERS_INTERVAL {
+            self.counters.log();
+            self.last_counters = Instant::now();
+        }
+    }
+
+    pub fn handle(&mut self, datagram: &Datagram) {
+        self.counters.datagrams += 1;
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = recorder.record(datagram) {
+                let error = Error::Recording(error);
+
+                error!(kind = error.kind(), %error, "unable to record datagram");
+            }
+        }
+
+        let packet = match Packet::parse(datagram.payload.as_bytes()) {
+            Ok(packet) => packet,
+            Err(error) => {
+                let error = Error::from(error);
+
+                match error {
+                    Error::Packet(core::error::Error::UnknownPacketType(ref kind)) => {
+                        self.counters.unknown_packets += 1;
+                        debug!(
+                            address = %datagram.address,
+                            packet_type = kind.as_str(),
+                            "ignoring packet of unknown type"
+                        );
+                    }
+                    _ => {
+                        self.counters.parse_failures += 1;
+                        warn!(
+                            address = %datagram.address,
+                            kind = error.kind(),
+                            %error,
+                            payload = datagram.payload.as_str(),
+                            "unable to parse datagram"
+                        );
+                    }
+                }
+
+                return;
+            }
+        };
+
+        if !self.filter.accepts(&packet) {
+            self.counters.filtered += 1;
+            return;
+        }
+
+        self.counters.packets += 1;
+        log_packet(&packet, &datagram.payload);
+        self.store(&packet);
+    }
+
+    /// Stores everything a packet carries.
+    fn store(&mut self, packet: &Packet) {
+        let weather = packet
+            .into_weather()
+            .or_else(|| match packet.into_partial_weather() {
+                Some(part) => self.merger.merge(part),
+                None => {
+                    if matches!(
+                        packet,
+                        Packet::AirObservation { .. } | Packet::SkyObservation { .. }
+                    ) {
+                        debug!(
+                            serial_number = packet.serial_number(),
+                            "dropping an AIR or SKY observation with a required value missing"
+                        );
+                    }
+                    None
+                }
+            });
+
+        if let Some(weather) = weather {
+            match self.conn.insert_observation(&weather) {
+                Ok(true) => {
+                    if let Some(uploader) = self.uploader.as_ref() {
+                        if let Err(error) = uploader.enqueue_weather(&self.conn, &weather) {
+                            self.database_error("enqueue_upload", error);
+                        }
+                    }
+                }
+                Ok(false) => debug!(
+                    serial_number = weather.serial_number.as_str(),
+                    time_epoch = weather.time_epoch,
+                    "skipping duplicate observation"
+                ),
+                Err(error) => self.database_error("insert_observation", error),
+            }
+        }
+
+        if let Some(sample) = packet.into_rapid_wind() {
+            if let Err(error) = self.conn.insert_rapid_wind(&sample) {
+                self.database_error("insert_rapid_wind", error);
+            }
+        }
+
+        if let Some(strike) = packet.into_lightning_strike() {
+            if let Err(error) = self.conn.insert_lightning_strike(&strike) {
+                self.database_error("insert_lightning_strike", error);
+            }
+        }
+
+        if let Some(rain_start) = packet.into_rain_start() {
+            if let Err(error) = self.conn.insert_rain_start(&rain_start) {
+                self.database_error("insert_rain_start", error);
+            }
+        }
+
+        if let Some(status) = packet.into_device_status() {
+            if let Err(error) = self.conn.insert_device_status(&status) {
+                self.database_error("insert_device_status", error);
+            }
+        }
+
+        if let Some(status) = packet.into_hub_status() {
+            if let Err(error) = self.conn.insert_hub_status(&status) {
+                self.database_error("insert_hub_status", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{self, TEST_RECORDING};
+    use db::{DatabaseLocation, GetObservations, GetRainStarts, GetRapidWind};
+    use std::path::Path;
+    use std::sync::mpsc;
+
+    fn pipeline(serials: Vec<String>) -> Pipeline {
+        Pipeline::new(
+            db::connect(&DatabaseLocation::InMemory).unwrap(),
+            SerialFilter::new(serials),
+            WeatherMerger::new(),
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn replay_into(pipeline: &mut Pipeline) {
+        let (sender, receiver) = mpsc::channel();
+
+        record::replay(Path::new(TEST_RECORDING), None, sender).unwrap();
+        for datagram in receiver.iter() {
+            pipeline.handle(&datagram);
+        }
+    }
+
+    #[test]
+    fn stores_a_replayed_recording() {
+        let mut pipeline = pipeline(Vec::new());
+        replay_into(&mut pipeline);
+
+        let counters = pipeline.counters();
+        assert_eq!(counters.datagrams, 12);
+        assert_eq!(counters.packets, 10);
+        assert_eq!(counters.filtered, 0);
+        assert_eq!(counters.parse_failures, 1);
+        assert_eq!(counters.unknown_packets, 1);
+        assert_eq!(counters.db_errors, 0);
+
+        let observations = pipeline.conn.get_observations(10, None).unwrap();
+        let serials: Vec<&str> = observations
+            .iter()
+            .map(|weather| weather.serial_number.as_str())
+            .collect();
+        assert_eq!(serials.len(), 2);
+        assert!(serials.contains(&"ST-00000001"));
+        assert!(serials.contains(&"AR-00004049/SK-00008453"));
+
+        assert_eq!(
+            pipeline
+                .conn
+                .get_rapid_wind(0, u64::MAX >> 1)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            pipeline
+                .conn
+                .get_rain_starts(0, u64::MAX >> 1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn filters_a_replayed_recording_by_serial() {
+        let mut pipeline = pipeline(vec!["AR-00004049".to_string()]);
+        replay_into(&mut pipeline);
+
+        assert_eq!(pipeline.counters().packets, 1);
+        assert_eq!(pipeline.counters().filtered, 9);
+        assert!(pipeline.conn.get_observations(10, None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// A datagram as it was received, and as it's stored in recordings, one JSON object per line.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Datagram {
+    /// When the datagram arrived, in milliseconds since the Unix epoch.
+    pub received_epoch_ms: u64,
+    /// The address of the socket it arrived on.
+    pub address: SocketAddr,
+    /// The datagram's contents. Packets are JSON, so this is kept as text, with any invalid UTF-8
+    /// replaced.
+    pub payload: String,
+}
+
+impl Datagram {
+    /// A datagram received just now.
+    pub fn received(address: SocketAddr, payload: &[u8]) -> Self {
+        let received_epoch_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+
+        Self {
+            received_epoch_ms,
+            address,
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        }
+    }
+}
+
+/// Appends every datagram received to a recording.
+pub struct Recorder {
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    /// Opens a recording to append to, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: LineWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, datagram: &Datagram) -> io::Result<()> {
+        let line = serde_json::to_string(datagram).expect("datagrams always serialize");
+
+        writeln!(self.file, "{}", line)
+    }
+}
+
+/**
+Sends every datagram in a recording to `sender`, in order, then returns.
+
+With a `speed`, datagrams are spaced out like they were received, sped up by that factor, so 1.0
+plays a recording back in real time. Without one they're sent as fast as they can be handled.
+Lines that can't be read as datagrams are reported and skipped.
+*/
+pub fn replay(path: &Path, speed: Option<f64>, sender: Sender<Datagram>) -> io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut start: Option<(Instant, u64)> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let datagram: Datagram = match serde_json::from_str(&line) {
+            Ok(datagram) => datagram,
+            Err(error) => {
+                warn!(
+                    path = %path.display(),
+                    line = index + 1,
+                    %error,
+                    "skipping unreadable line"
+                );
+                continue;
+            }
+        };
+
+        if let Some(speed) = speed {
+            let (started, first_epoch_ms) =
+                *start.get_or_insert((Instant::now(), datagram.received_epoch_ms));
+            let offset = datagram.received_epoch_ms.saturating_sub(first_epoch_ms);
+            let due = started + Duration::from_millis(offset).div_f64(speed);
+
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+
+        if sender.send(datagram).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// A short recording with one of each kind of packet, plus a few datagrams and lines that can't
+/// be read.
+#[cfg(test)]
+pub(crate) const TEST_RECORDING: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/replay.jsonl");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn replay_all(speed: Option<f64>) -> Vec<Datagram> {
+        let (sender, receiver) = mpsc::channel();
+
+        replay(Path::new(TEST_RECORDING), speed, sender).unwrap();
+        receiver.iter().collect()
+    }
+
+    #[test]
+    fn replays_every_readable_line_in_order() {
+        let datagrams = replay_all(None);
+
+        assert_eq!(datagrams.len(), 12);
+        assert!(datagrams
+            .windows(2)
+            .all(|pair| pair[0].received_epoch_ms < pair[1].received_epoch_ms));
+        assert!(datagrams[0].payload.contains("rapid_wind"));
+    }
+
+    #[test]
+    fn replays_at_the_recorded_pace() {
+        let started = Instant::now();
+        let datagrams = replay_all(Some(200.0));
+        let recorded = datagrams.last().unwrap().received_epoch_ms - datagrams[0].received_epoch_ms;
+
+        assert!(started.elapsed() >= Duration::from_millis(recorded / 200));
+    }
+
+    #[test]
+    fn fails_to_replay_a_missing_file() {
+        let (sender, _receiver) = mpsc::channel();
+        let error = replay(Path::new("testdata/missing.jsonl"), None, sender).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn records_datagrams_that_replay_the_same() {
+        let path =
+            std::env::temp_dir().join(format!("tempestrs-record-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let datagrams = replay_all(None);
+
+        let mut recorder = Recorder::open(&path).unwrap();
+        for datagram in &datagrams {
+            recorder.record(datagram).unwrap();
+        }
+        drop(recorder);
+
+        let (sender, receiver) = mpsc::channel();
+        replay(&path, None, sender).unwrap();
+        let replayed: Vec<Datagram> = receiver.iter().collect();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(replayed.len(), datagrams.len());
+        assert!(replayed
+            .iter()
+            .zip(&datagrams)
+            .all(|(a, b)| a.payload == b.payload && a.received_epoch_ms == b.received_epoch_ms));
+    }
+}
@@ -0,0 +1,155 @@
+use core::weather::Weather;
+use db::{Connection, Outbox};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// The path observations are posted to on the worker.
+const WEATHER_PATH: &str = "/weather";
+
+/// How many queued records are sent at a time, so catching up after an outage doesn't hold up
+/// recording new packets for long.
+const BATCH_SIZE: usize = 100;
+
+/// How long to wait for the server before counting an upload as failed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait after the first failure before trying again. Each consecutive failure doubles
+/// the wait, up to `MAX_RETRY_DELAY`.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(15);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// Why a record couldn't be uploaded.
+enum UploadError {
+    /// The server rejected the record itself, so sending it again won't help.
+    Rejected(String),
+    /// The network or the server is down; the record should be sent again later.
+    Unavailable(String),
+}
+
+/**
+Forwards stored records to the worker's HTTP API.
+
+Records are queued in the database's outbox as they're stored, and sent in order whenever the
+uploader is flushed. When an upload fails because the network or the server is down, the record
+stays queued and nothing more is sent until a backoff delay has passed, so the cloud copy catches
+up once the outage is over without hammering the server in the meantime.
+*/
+pub struct Uploader {
+    url: String,
+    agent: ureq::Agent,
+    consecutive_failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Uploader {
+    /// Creates an uploader posting to the worker at `url`, e.g. "https://weather.example.com".
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            consecutive_failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Queues an observation to be uploaded.
+    pub fn enqueue_weather(&self, conn: &Connection, weather: &Weather) -> Result<(), db::Error> {
+        let body = serde_json::to_string(weather).expect("observations always serialize");
+
+        Ok(conn.enqueue(WEATHER_PATH, &body)?)
+    }
+
+    /// Sends a batch of queued records, unless waiting out the backoff from an earlier failure.
+    pub fn flush(&mut self, conn: &Connection) -> Result<(), db::Error> {
+        if self.retry_at.is_some_and(|time| Instant::now() < time) {
+            return Ok(());
+        }
+
+        for entry in conn.get_outbox(BATCH_SIZE)? {
+            match self.post(&entry.path, &entry.body) {
+                Ok(()) => {
+                    conn.remove_from_outbox(entry.id)?;
+
+                    if self.consecutive_failures > 0 {
+                        info!("upload server is reachable again");
+                    }
+
+                    self.consecutive_failures = 0;
+                    self.retry_at = None;
+                }
+                Err(UploadError::Rejected(error)) => {
+                    warn!(
+                        path = entry.path.as_str(),
+                        error, "dropping rejected record"
+                    );
+                    conn.remove_from_outbox(entry.id)?;
+                }
+                Err(UploadError::Unavailable(error)) => {
+                    conn.record_outbox_failure(entry.id, &error)?;
+                    self.consecutive_failures += 1;
+
+                    let delay = retry_delay(self.consecutive_failures);
+                    warn!(
+                        error,
+                        queued = conn.count_outbox()?,
+                        retry_in_secs = delay.as_secs(),
+                        "unable to upload"
+                    );
+
+                    self.retry_at = Some(Instant::now() + delay);
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn post(&self, path: &str, body: &str) -> Result<(), UploadError> {
+        let result = self
+            .agent
+            .post(&format!("{}{}", self.url, path))
+            .set("Content-Type", "application/json")
+            .send_string(body);
+
+        match result {
+            Ok(_) => Ok(()),
+            // Only these mean the record itself is bad. Anything else, including a 404 from a
+            // mistyped URL, is worth retrying once the problem is fixed.
+            Err(ureq::Error::Status(status @ (400 | 413 | 415 | 422), response)) => {
+                Err(UploadError::Rejected(format!(
+                    "{} {}",
+                    status,
+                    response.into_string().unwrap_or_default()
+                )))
+            }
+            Err(ureq::Error::Status(status, _)) => Err(UploadError::Unavailable(format!(
+                "server responded with {}",
+                status
+            ))),
+            Err(error) => Err(UploadError::Unavailable(error.to_string())),
+        }
+    }
+}
+
+/// How long to wait before trying again after the given number of consecutive failures.
+fn retry_delay(consecutive_failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(consecutive_failures.saturating_sub(1));
+
+    MIN_RETRY_DELAY.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_the_retry_delay_up_to_the_maximum() {
+        assert_eq!(retry_delay(0), MIN_RETRY_DELAY);
+        assert_eq!(retry_delay(1), MIN_RETRY_DELAY);
+        assert_eq!(retry_delay(2), MIN_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(3), MIN_RETRY_DELAY * 4);
+        assert_eq!(retry_delay(20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
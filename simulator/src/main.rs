@@ -0,0 +1,119 @@
+use clap::Parser;
+use core::packet::Packet;
+use core::util::now_epoch;
+use core::weather::IntoWeather;
+use model::{Simulation, STEP};
+use std::net::{SocketAddr, UdpSocket};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod model;
+
+#[derive(Parser)]
+#[command(about = "Broadcast synthetic Tempest packets, for testing without a station")]
+struct Args {
+    /// Address to send packets to. Hubs broadcast to 255.255.255.255:50222
+    #[arg(long, default_value = "127.0.0.1:50222")]
+    target: SocketAddr,
+    /// How many times faster than real time to run
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f64,
+    /// When the simulation starts, as a Unix epoch. Defaults to now
+    #[arg(long)]
+    start: Option<u64>,
+    /// How many simulated hours to run for. Runs until interrupted by default
+    #[arg(long)]
+    hours: Option<f64>,
+    /// Serial number of the simulated device
+    #[arg(long, default_value = "ST-00000001")]
+    serial: String,
+    /// Serial number of the simulated hub
+    #[arg(long, default_value = "HB-00000001")]
+    hub: String,
+    /// Seed for the random weather, to generate the same weather every run
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Don't print a summary of each observation
+    #[arg(long)]
+    quiet: bool,
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("expected a positive number, got {}", value)),
+    }
+}
+
+/// Summarizes an observation packet as a single line.
+fn describe(packet: &Packet, sky: &str) -> Option<String> {
+    let weather = packet.into_weather()?;
+
+    Some(format!(
+        "{}  {}  {:.0}%  {}  wind {} gust {} {}  rain {}  {}",
+        weather.get_time().unwrap().format("%b %-d %-I:%M %p"),
+        weather.get_air_temp(),
+        weather.relative_humidity,
+        weather.get_station_pressure(),
+        weather.get_wind_avg(),
+        weather.get_wind_gust(),
+        weather.get_wind_direction(),
+        weather.get_rain_over_prev_minute(),
+        sky
+    ))
+}
+
+fn main() {
+    let args = Args::parse();
+    let local: SocketAddr = if args.target.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = match UdpSocket::bind(local).and_then(|socket| {
+        socket.set_broadcast(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => socket,
+        Err(error) => {
+            eprintln!("Unable to open socket: {}", error);
+            process::exit(1);
+        }
+    };
+
+    let start = args.start.unwrap_or_else(now_epoch);
+    let end = args
+        .hours
+        .map(|hours| start + (hours * 3600.0).round() as u64);
+    let mut simulation = Simulation::new(&args.serial, &args.hub, start, args.seed);
+
+    println!(
+        "Sending packets from {} to {} at {}x speed",
+        args.serial, args.target, args.speed
+    );
+
+    let started = Instant::now();
+    let mut steps: u32 = 0;
+
+    while end.is_none_or(|end| simulation.time() < end) {
+        for packet in simulation.step() {
+            let payload = serde_json::to_vec(&packet).expect("packets always serialize");
+
+            if let Err(error) = socket.send_to(&payload, args.target) {
+                eprintln!("Unable to send packet: {}", error);
+            }
+
+            if !args.quiet {
+                if let Some(summary) = describe(&packet, simulation.sky().name()) {
+                    println!("{}", summary);
+                }
+            }
+        }
+
+        steps += 1;
+
+        let due = started + Duration::from_secs(STEP).mul_f64(steps as f64 / args.speed);
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+    }
+}
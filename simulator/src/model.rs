@@ -0,0 +1,434 @@
+use chrono::{Local, TimeZone, Timelike};
+use core::packet::Packet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+
+/// Seconds between wind samples, which is also how far each step advances the simulation.
+pub const STEP: u64 = 3;
+
+/// Seconds between observations. Devices report every minute.
+const REPORT_INTERVAL: u64 = 60;
+
+/// Seconds between hub status reports.
+const HUB_STATUS_INTERVAL: u64 = 10;
+
+const FIRMWARE_REVISION: u64 = 176;
+const HUB_FIRMWARE_REVISION: &str = "194";
+
+/// What the sky is doing. Showers and thunderstorms run from `start` to `end` and peak halfway
+/// through, at `intensity` mm of rain per minute.
+#[derive(Debug, Clone, Copy)]
+pub enum Sky {
+    Clear,
+    Shower {
+        start: u64,
+        end: u64,
+        intensity: f64,
+    },
+    Thunderstorm {
+        start: u64,
+        end: u64,
+        intensity: f64,
+    },
+}
+
+impl Sky {
+    /// How far through the shower or storm `time` is, from 0 to 1, and its peak intensity.
+    fn progress(&self, time: u64) -> Option<(f64, f64)> {
+        match *self {
+            Sky::Clear => None,
+            Sky::Shower {
+                start,
+                end,
+                intensity,
+            }
+            | Sky::Thunderstorm {
+                start,
+                end,
+                intensity,
+            } => Some(((time - start) as f64 / (end - start) as f64, intensity)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sky::Clear => "clear",
+            Sky::Shower { .. } => "shower",
+            Sky::Thunderstorm { .. } => "thunderstorm",
+        }
+    }
+}
+
+/// Wind, rain and lightning accumulated over the current report interval.
+#[derive(Default)]
+struct Interval {
+    wind_speeds: Vec<f64>,
+    /// The sum of the wind vectors, for the average direction.
+    wind_x: f64,
+    wind_y: f64,
+    rain: f64,
+    strike_distances: Vec<u64>,
+}
+
+/**
+A Tempest device and its hub, generating the packets they'd broadcast.
+
+The weather follows a diurnal cycle: temperature peaks mid-afternoon and bottoms out before dawn,
+humidity does the opposite, and the wind picks up during the day. Showers and thunderstorms pop up
+at random, more often in the afternoon, bringing rain, cooler and more humid air, stronger and
+gustier wind, a dip in pressure, and for thunderstorms, lightning that approaches and then moves
+away.
+*/
+pub struct Simulation {
+    rng: StdRng,
+    serial_number: String,
+    hub_sn: String,
+    time: u64,
+    start: u64,
+    sky: Sky,
+    /// Slowly wandering departures from the typical temperature and sea-level pressure.
+    temp_anomaly: f64,
+    pressure_anomaly: f64,
+    /// How much a shower or storm is currently cooling, moistening and depressurizing the air.
+    storm_effect: f64,
+    prevailing_direction: f64,
+    interval: Interval,
+    hub_seq: u64,
+}
+
+impl Simulation {
+    /// Starts a simulation at `start`, a Unix epoch. A seed makes it generate the same weather
+    /// every time.
+    pub fn new(serial_number: &str, hub_sn: &str, start: u64, seed: Option<u64>) -> Self {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Self {
+            serial_number: serial_number.to_string(),
+            hub_sn: hub_sn.to_string(),
+            time: start,
+            start,
+            sky: Sky::Clear,
+            temp_anomaly: rng.gen_range(-3.0..3.0),
+            pressure_anomaly: rng.gen_range(-8.0..8.0),
+            storm_effect: 0.0,
+            prevailing_direction: rng.gen_range(0.0..360.0),
+            interval: Interval::default(),
+            hub_seq: 0,
+            rng,
+        }
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn sky(&self) -> Sky {
+        self.sky
+    }
+
+    /// Advances the simulation by one wind sample, returning the packets sent in that time.
+    pub fn step(&mut self) -> Vec<Packet> {
+        self.time += STEP;
+
+        let mut packets = Vec::new();
+
+        if let Some(packet) = self.update_sky() {
+            packets.push(packet);
+        }
+
+        self.update_air();
+        packets.push(self.sample_wind());
+        self.sample_rain();
+        packets.extend(self.sample_lightning());
+
+        if self.crossed(REPORT_INTERVAL) {
+            packets.push(self.observation());
+            packets.push(self.device_status());
+        }
+
+        if self.crossed(HUB_STATUS_INTERVAL) {
+            packets.push(self.hub_status());
+        }
+
+        packets
+    }
+
+    /// Whether the last step crossed into a new multiple of `interval` seconds.
+    fn crossed(&self, interval: u64) -> bool {
+        self.time / interval != (self.time - STEP) / interval
+    }
+
+    /// The local time of day in hours, e.g. 14.5 for 2:30 PM.
+    fn hour(&self) -> f64 {
+        let time = Local.timestamp_opt(self.time as i64, 0).unwrap();
+
+        time.num_seconds_from_midnight() as f64 / 3600.0
+    }
+
+    /// How high the sun is, from 0 at night to 1 at noon.
+    fn daylight(&self) -> f64 {
+        (PI * (self.hour() - 6.0) / 12.0).sin().max(0.0)
+    }
+
+    /// A normally distributed random number with mean 0 and standard deviation 1.
+    fn gaussian(&mut self) -> f64 {
+        let u1: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let u2: f64 = self.rng.gen();
+
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+
+    /// Ends a shower or storm that's over, or maybe starts one, returning the rain start event if
+    /// one did.
+    fn update_sky(&mut self) -> Option<Packet> {
+        if let Some((progress, _)) = self.sky.progress(self.time) {
+            if progress >= 1.0 {
+                self.sky = Sky::Clear;
+            }
+
+            return None;
+        }
+
+        // Chances per hour, with convection making storms far likelier in the afternoon.
+        let afternoon = (13.0..20.0).contains(&self.hour());
+        let shower_rate = 0.04;
+        let storm_rate = if afternoon { 0.12 } else { 0.01 };
+        let roll: f64 = self.rng.gen::<f64>() * 3600.0 / STEP as f64;
+
+        self.sky = if roll < storm_rate {
+            let duration = self.rng.gen_range(30..90) * 60;
+
+            Sky::Thunderstorm {
+                start: self.time,
+                end: self.time + duration,
+                intensity: self.rng.gen_range(0.3..1.5),
+            }
+        } else if roll < storm_rate + shower_rate {
+            let duration = self.rng.gen_range(10..60) * 60;
+
+            Sky::Shower {
+                start: self.time,
+                end: self.time + duration,
+                intensity: self.rng.gen_range(0.03..0.3),
+            }
+        } else {
+            return None;
+        };
+
+        Some(Packet::EventRainStart {
+            serial_number: self.serial_number.clone(),
+            hub_sn: self.hub_sn.clone(),
+            evt: [self.time],
+        })
+    }
+
+    fn update_air(&mut self) {
+        let target_effect = match self.sky {
+            Sky::Clear => 0.0,
+            Sky::Shower { .. } => 0.5,
+            Sky::Thunderstorm { .. } => 1.0,
+        };
+
+        // Anomalies drift back towards zero over a few hours; storm effects come and go over
+        // about ten minutes.
+        self.temp_anomaly += -self.temp_anomaly * 0.0005 + self.gaussian() * 0.01;
+        self.pressure_anomaly += -self.pressure_anomaly * 0.0002 + self.gaussian() * 0.01;
+        self.storm_effect += (target_effect - self.storm_effect) * 0.005;
+        self.prevailing_direction =
+            (self.prevailing_direction + self.gaussian() * 0.3).rem_euclid(360.0);
+    }
+
+    /// The typical temperature at this time of day, peaking mid-afternoon.
+    fn diurnal_temp(&self) -> f64 {
+        15.0 + 7.0 * (2.0 * PI * (self.hour() - 9.0) / 24.0).sin()
+    }
+
+    fn air_temp(&self) -> f64 {
+        self.diurnal_temp() + self.temp_anomaly - 6.0 * self.storm_effect
+    }
+
+    fn relative_humidity(&self) -> f64 {
+        let temp = self.diurnal_temp() + self.temp_anomaly;
+
+        (70.0 - 3.0 * (temp - 15.0) + 35.0 * self.storm_effect).clamp(12.0, 100.0)
+    }
+
+    /// Station pressure for a station about 100 m above sea level.
+    fn station_pressure(&self) -> f64 {
+        1001.0 + self.pressure_anomaly - 3.0 * self.storm_effect
+    }
+
+    fn solar_radiation(&self) -> f64 {
+        let clouds = match self.sky {
+            Sky::Clear => 0.9,
+            Sky::Shower { .. } => 0.3,
+            Sky::Thunderstorm { .. } => 0.1,
+        };
+
+        1000.0 * self.daylight() * clouds
+    }
+
+    fn sample_wind(&mut self) -> Packet {
+        let storm_wind = match self.sky {
+            Sky::Clear => 0.0,
+            Sky::Shower { .. } => 2.0,
+            Sky::Thunderstorm { .. } => 6.0,
+        };
+        let mean = 1.0 + 3.0 * self.daylight() + storm_wind;
+        let mut speed = mean * (1.0 + 0.35 * self.gaussian());
+
+        if self.rng.gen_bool(0.05) {
+            speed += mean * self.rng.gen_range(0.5..1.5);
+        }
+
+        let speed = speed.max(0.0);
+        // Light wind wanders more.
+        let spread = 15.0 + 30.0 / (1.0 + speed);
+        let direction = (self.prevailing_direction + self.gaussian() * spread).rem_euclid(360.0);
+
+        self.interval.wind_speeds.push(speed);
+        self.interval.wind_x += speed * direction.to_radians().sin();
+        self.interval.wind_y += speed * direction.to_radians().cos();
+
+        Packet::RapidWind {
+            serial_number: self.serial_number.clone(),
+            hub_sn: self.hub_sn.clone(),
+            ob: (self.time, round(speed, 2), direction.round() as u64 % 360),
+        }
+    }
+
+    fn sample_rain(&mut self) {
+        let Some((progress, intensity)) = self.sky.progress(self.time) else {
+            return;
+        };
+
+        let burst = self.rng.gen_range(0.3..1.7);
+        let rate = intensity * (PI * progress).sin() * burst;
+
+        self.interval.rain += rate * STEP as f64 / 60.0;
+    }
+
+    /// Strikes in a thunderstorm, which is most active, and closest, halfway through.
+    fn sample_lightning(&mut self) -> Option<Packet> {
+        let Sky::Thunderstorm { .. } = self.sky else {
+            return None;
+        };
+        let (progress, intensity) = self.sky.progress(self.time)?;
+
+        let strikes_per_minute = 4.0 * intensity * (PI * progress).sin();
+        if !self
+            .rng
+            .gen_bool((strikes_per_minute * STEP as f64 / 60.0).clamp(0.0, 0.9))
+        {
+            return None;
+        }
+
+        let distance = (1.0 + 35.0 * (2.0 * progress - 1.0).abs() + self.gaussian() * 2.0)
+            .clamp(1.0, 40.0)
+            .round() as u64;
+        let energy = 10f64.powf(self.rng.gen_range(2.0..6.0)).round() as u64;
+
+        self.interval.strike_distances.push(distance);
+
+        Some(Packet::EventLightningStrike {
+            serial_number: self.serial_number.clone(),
+            hub_sn: self.hub_sn.clone(),
+            evt: [self.time, distance, energy],
+        })
+    }
+
+    fn observation(&mut self) -> Packet {
+        let interval = std::mem::take(&mut self.interval);
+        let speeds = &interval.wind_speeds;
+        let lull = speeds.iter().copied().fold(f64::INFINITY, f64::min);
+        let gust = speeds.iter().copied().fold(0.0, f64::max);
+        let avg = speeds.iter().sum::<f64>() / speeds.len() as f64;
+        let direction = interval
+            .wind_x
+            .atan2(interval.wind_y)
+            .to_degrees()
+            .rem_euclid(360.0);
+
+        let solar_radiation = self.solar_radiation();
+        let precip_type = match self.sky {
+            _ if interval.rain <= 0.0 => 0.0,
+            Sky::Thunderstorm { intensity, .. } if intensity > 1.2 && self.rng.gen_bool(0.2) => 3.0,
+            _ => 1.0,
+        };
+        let strike_count = interval.strike_distances.len() as u64;
+        let strike_distance = interval
+            .strike_distances
+            .iter()
+            .sum::<u64>()
+            .checked_div(strike_count)
+            .unwrap_or(0);
+
+        Packet::Observation {
+            serial_number: self.serial_number.clone(),
+            hub_sn: self.hub_sn.clone(),
+            firmware_revision: FIRMWARE_REVISION,
+            obs: [[
+                self.time as f64,
+                round(lull, 2),
+                round(avg, 2),
+                round(gust, 2),
+                direction.round() % 360.0,
+                STEP as f64,
+                round(self.station_pressure(), 2),
+                round(self.air_temp(), 2),
+                round(self.relative_humidity(), 0),
+                round(solar_radiation * 120.0, 0),
+                round(solar_radiation / 90.0, 2),
+                round(solar_radiation, 0),
+                round(interval.rain, 3),
+                precip_type,
+                strike_distance as f64,
+                strike_count as f64,
+                round(2.55 + 0.1 * self.daylight(), 3),
+                (REPORT_INTERVAL / 60) as f64,
+            ]],
+        }
+    }
+
+    fn device_status(&mut self) -> Packet {
+        Packet::DeviceStatus {
+            serial_number: self.serial_number.clone(),
+            hub_sn: self.hub_sn.clone(),
+            timestamp: self.time,
+            uptime: self.time - self.start,
+            voltage: round(2.55 + 0.1 * self.daylight(), 3),
+            firmware_revision: FIRMWARE_REVISION,
+            rssi: -70 + self.rng.gen_range(-4..=4),
+            hub_rssi: -72 + self.rng.gen_range(-4..=4),
+            sensor_status: 0,
+            debug: false,
+        }
+    }
+
+    fn hub_status(&mut self) -> Packet {
+        self.hub_seq += 1;
+
+        Packet::HubStatus {
+            serial_number: self.hub_sn.clone(),
+            firmware_revision: HUB_FIRMWARE_REVISION.to_string(),
+            uptime: self.time - self.start,
+            rssi: -45 + self.rng.gen_range(-3..=3),
+            timestamp: self.time,
+            reset_flags: "BOR,PIN,POR".to_string(),
+            seq: self.hub_seq,
+            radio_stats: [25, 1, 0, 3, 16_355],
+            mqtt_stats: [1, 0],
+        }
+    }
+}
+
+/// Rounds to the given number of decimal places, like the devices do.
+fn round(value: f64, places: i32) -> f64 {
+    let factor = 10f64.powi(places);
+
+    (value * factor).round() / factor
+}
@@ -1,46 +1,389 @@
-use core::{queries::QUERY_INSERT_OBSERVATION, weather::Weather};
+use chrono::FixedOffset;
+use core::{
+    queries::{
+        pending_migrations, ObservationRange, SortOrder, QUERY_CREATE_TABLE_SCHEMA_VERSION,
+        QUERY_INSERT_DEVICE_STATUS, QUERY_INSERT_OBSERVATION, QUERY_INSERT_SCHEMA_VERSION,
+        QUERY_SELECT_OBSERVATIONS, QUERY_SELECT_PRESSURE_SAMPLES, QUERY_SELECT_RAIN_TOTALS,
+        QUERY_SELECT_SCHEMA_VERSION,
+    },
+    rain::{RainPeriods, RainSums, RainTotals},
+    station::StationConfig,
+    status::{DeviceStatus, SensorFlags},
+    trend::{PressureSample, PressureTrend, DEFAULT_TREND_WINDOW},
+    units::UnitPreferences,
+    weather::{Weather, WeatherReport},
+};
+use serde::{de::IgnoredAny, Serialize};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use worker::*;
 
-async fn handle_get_weather_latest(db: &D1Database) -> Result<Response> {
+/// Whether this isolate has already brought the database schema up to date.
+static MIGRATED: AtomicBool = AtomicBool::new(false);
+
+/// Reads the version of the newest migration applied to the database.
+async fn schema_version(db: &D1Database) -> Result<u32> {
+    Ok(db
+        .prepare(QUERY_SELECT_SCHEMA_VERSION)
+        .first::<u32>(Some("version"))
+        .await?
+        .unwrap_or(0))
+}
+
+/**
+Applies every pending migration, each as a single D1 batch so that it's atomic, the same way the
+`db` crate does against SQLite. SQLite-only migrations are only recorded.
+
+Several isolates may start migrating at once. Each batch records its version first, and versions
+are unique, so only one isolate's batch for a step can succeed; the others fail and are rolled
+back, and are ignored once the step turns out to have been applied.
+*/
+async fn migrate(db: &D1Database) -> Result<()> {
+    db.prepare(QUERY_CREATE_TABLE_SCHEMA_VERSION).run().await?;
+
+    let version = schema_version(db).await?;
+
+    for migration in pending_migrations(version) {
+        let mut statements = vec![db.prepare(QUERY_INSERT_SCHEMA_VERSION).bind(&[
+            migration.version.into(),
+            migration.description.into(),
+            ((Date::now().as_millis() / 1000) as f64).into(),
+        ])?];
+
+        if !migration.sqlite_only {
+            statements.extend(
+                migration
+                    .statements
+                    .iter()
+                    .map(|statement| db.prepare(*statement)),
+            );
+        }
+
+        if let Err(error) = db.batch(statements).await {
+            if schema_version(db).await? < migration.version {
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The response to a `POST`, saying whether the record was new or a duplicate of one already
+/// stored.
+#[derive(Serialize)]
+struct InsertResponse {
+    inserted: bool,
+}
+
+/// Runs an `INSERT ... ON CONFLICT DO NOTHING RETURNING id` statement, responding with 201 if a
+/// row was inserted and 200 if it was a duplicate, so clients can safely retry.
+async fn insert_if_new(statement: D1PreparedStatement) -> Result<Response> {
+    let inserted = !statement.all().await?.results::<IgnoredAny>()?.is_empty();
+    let status = if inserted { 201 } else { 200 };
+
+    Ok(Response::from_json(&InsertResponse { inserted })?.with_status(status))
+}
+
+/// A device status report along with its decoded sensor flags.
+#[derive(Serialize)]
+struct DeviceStatusResponse {
+    #[serde(flatten)]
+    status: DeviceStatus,
+    sensor_flags: SensorFlags,
+}
+
+/// Reads the station's location from the `STATION_ELEVATION`, `STATION_LATITUDE` and
+/// `STATION_LONGITUDE` variables, and its rain day from `STATION_RAIN_DAY_START`. Without an
+/// elevation there's no station config.
+fn station_config(env: &Env) -> Option<StationConfig> {
+    let var = |name: &str| env.var(name).ok().map(|value| value.to_string());
+
+    Some(StationConfig {
+        elevation: var("STATION_ELEVATION")?.parse().ok()?,
+        latitude: var("STATION_LATITUDE")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0.0),
+        longitude: var("STATION_LONGITUDE")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0.0),
+        rain_day_start: var("STATION_RAIN_DAY_START")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+/// The most observations `GET /weather` returns in one page.
+const MAX_PAGE_SIZE: usize = 1440;
+
+fn query_params(req: &Request) -> Result<HashMap<String, String>> {
+    Ok(req.url()?.query_pairs().into_owned().collect())
+}
+
+fn parse_param<T: FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> std::result::Result<Option<T>, String> {
+    params
+        .get(name)
+        .map(|value| value.parse::<T>())
+        .transpose()
+        .map_err(|_| format!("Invalid value for {}.", name))
+}
+
+fn parse_observation_range(
+    params: &HashMap<String, String>,
+) -> std::result::Result<ObservationRange, String> {
+    let mut range = ObservationRange::new(parse_param(params, "from")?, parse_param(params, "to")?);
+
+    range.serial_number = params.get("serial_number").cloned();
+    range.order = match params.get("order").map(String::as_str) {
+        Some("asc") => SortOrder::Ascending,
+        Some("desc") | None => SortOrder::Descending,
+        Some(_) => return Err("Invalid value for order, expected asc or desc.".to_string()),
+    };
+    range.limit = Some(
+        parse_param(params, "limit")?
+            .unwrap_or(MAX_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE),
+    );
+    range.offset = parse_param(params, "offset")?.unwrap_or(0);
+
+    Ok(range)
+}
+
+/// Parses the `units` parameter, which takes the same values as the CLI's `--units`.
+fn parse_units(
+    params: &HashMap<String, String>,
+) -> std::result::Result<Option<UnitPreferences>, String> {
+    params
+        .get("units")
+        .map(|units| units.parse::<UnitPreferences>())
+        .transpose()
+        .map_err(|message| format!("Invalid value for units: {}.", message))
+}
+
+async fn handle_get_weather(
+    req: Request,
+    db: &D1Database,
+    station: Option<&StationConfig>,
+) -> Result<Response> {
+    let params = query_params(&req)?;
+    let parsed =
+        parse_observation_range(&params).and_then(|range| Ok((range, parse_units(&params)?)));
+    let (range, units) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return Response::error(message, 400),
+    };
+    let (from, to, limit, offset) = range.bounds();
+
+    // D1 doesn't accept BigInts, so the bounds are bound as plain numbers.
+    let result = db
+        .prepare(range.query())
+        .bind(&[
+            (from as f64).into(),
+            (to as f64).into(),
+            range.serial_number.clone().into(),
+            (limit as f64).into(),
+            (offset as f64).into(),
+        ])?
+        .all()
+        .await?;
+
+    let observations: Vec<WeatherReport> = result
+        .results::<Weather>()?
+        .iter()
+        .map(|weather| WeatherReport::new(weather, station, None, units.as_ref()))
+        .collect();
+
+    Response::from_json(&observations)
+}
+
+async fn get_pressure_trend(
+    db: &D1Database,
+    weather: &Weather,
+    window: u64,
+) -> Result<Option<PressureTrend>> {
+    let samples = db
+        .prepare(QUERY_SELECT_PRESSURE_SAMPLES)
+        .bind(&[
+            (weather.time_epoch.saturating_sub(window) as f64).into(),
+            ((weather.time_epoch + 1) as f64).into(),
+            weather.serial_number.as_str().into(),
+        ])?
+        .all()
+        .await?
+        .results::<PressureSample>()?;
+
+    Ok(PressureTrend::from_samples(&samples, window))
+}
+
+async fn handle_get_weather_latest(
+    req: Request,
+    db: &D1Database,
+    station: Option<&StationConfig>,
+) -> Result<Response> {
+    let params = query_params(&req)?;
+    let parsed = parse_param::<u64>(&params, "trend_hours").and_then(|hours| {
+        let window = match hours {
+            Some(hours) => hours
+                .checked_mul(3600)
+                .ok_or_else(|| "Invalid value for trend_hours.".to_string())?,
+            None => DEFAULT_TREND_WINDOW,
+        };
+        Ok((window, parse_units(&params)?))
+    });
+    let (window, units) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return Response::error(message, 400),
+    };
     let weather_result = db
-        .prepare("SELECT * FROM observation ORDER BY id DESC LIMIT 1")
+        .prepare(QUERY_SELECT_OBSERVATIONS)
+        .bind(&[1.into(), params.get("serial_number").cloned().into()])?
         .first::<Weather>(None)
         .await?;
 
     match weather_result {
-        Some(weather) => Response::from_json(&weather),
+        Some(weather) => {
+            let pressure_trend = get_pressure_trend(db, &weather, window).await?;
+
+            Response::from_json(&WeatherReport::new(
+                &weather,
+                station,
+                pressure_trend.as_ref(),
+                units.as_ref(),
+            ))
+        }
         None => Response::error("No weather observations found.", 404),
     }
 }
 
-async fn handle_post_weather(mut req: Request, db: &D1Database) -> Result<Response> {
-    let weather: Weather = req.json().await?;
+/// Rain totals, converted to the requested units if there are any.
+#[derive(Serialize)]
+struct RainResponse {
+    #[serde(flatten)]
+    totals: RainTotals,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<UnitPreferences>,
+}
+
+/// Responds with rain totals for the station's rain day. Workers run in UTC, so the station's
+/// offset from UTC is given in minutes by `utc_offset`, and the hour its day starts at by
+/// `day_start`.
+async fn handle_get_rain(
+    req: Request,
+    db: &D1Database,
+    station: Option<&StationConfig>,
+) -> Result<Response> {
+    let params = query_params(&req)?;
+    let parsed = parse_param::<u32>(&params, "day_start").and_then(|day_start| {
+        let offset = parse_param::<i32>(&params, "utc_offset")?.unwrap_or(0);
+        let tz = FixedOffset::east_opt(offset * 60)
+            .ok_or_else(|| "Invalid value for utc_offset.".to_string())?;
+
+        match day_start.or(station.map(|station| station.rain_day_start)) {
+            Some(hour) if hour >= 24 => Err("Invalid value for day_start.".to_string()),
+            hour => Ok((hour.unwrap_or(0), tz, parse_units(&params)?)),
+        }
+    });
+    let (day_start, tz, units) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return Response::error(message, 400),
+    };
 
-    db.prepare(QUERY_INSERT_OBSERVATION)
+    let now = Date::now().as_millis() / 1000;
+    let periods = RainPeriods::new(now, day_start, &tz);
+    let sums = db
+        .prepare(QUERY_SELECT_RAIN_TOTALS)
         .bind(&[
-            weather.time_epoch.into(),
-            weather.wind_lull.into(),
-            weather.wind_avg.into(),
-            weather.wind_gust.into(),
-            weather.wind_direction.into(),
-            weather.wind_sample_interval.into(),
-            weather.station_pressure.into(),
-            weather.air_temp.into(),
-            weather.relative_humidity.into(),
-            weather.illuminance.into(),
-            weather.uv_index.into(),
-            weather.solar_radiation.into(),
-            weather.rain_over_prev_minute.into(),
-            weather.precip_type.into(),
-            weather.lightning_avg_distance.into(),
-            weather.lightning_strike_count.into(),
-            weather.battery_voltage.into(),
-            weather.report_interval.into(),
+            (periods.last_hour as f64).into(),
+            (periods.last_24h as f64).into(),
+            (periods.today as f64).into(),
+            (periods.yesterday as f64).into(),
+            (periods.month as f64).into(),
+            (periods.year as f64).into(),
+            (periods.end as f64).into(),
+            (periods.rate_start() as f64).into(),
+            params.get("serial_number").cloned().into(),
         ])?
-        .run()
+        .first::<RainSums>(None)
+        .await?
+        .unwrap_or_default();
+
+    let totals = RainTotals::from(sums);
+
+    Response::from_json(&RainResponse {
+        totals: units.map_or(totals, |units| totals.in_units(&units)),
+        units,
+    })
+}
+
+async fn handle_post_weather(mut req: Request, db: &D1Database) -> Result<Response> {
+    let weather: Weather = req.json().await?;
+
+    // D1 doesn't accept BigInts, so 64-bit integers are bound as plain numbers.
+    let statement = db.prepare(QUERY_INSERT_OBSERVATION).bind(&[
+        (weather.time_epoch as f64).into(),
+        weather.wind_lull.into(),
+        weather.wind_avg.into(),
+        weather.wind_gust.into(),
+        weather.wind_direction.into(),
+        weather.wind_sample_interval.into(),
+        weather.station_pressure.into(),
+        weather.air_temp.into(),
+        weather.relative_humidity.into(),
+        weather.illuminance.into(),
+        weather.uv_index.into(),
+        weather.solar_radiation.into(),
+        weather.rain_over_prev_minute.into(),
+        weather.precip_type.into(),
+        weather.lightning_avg_distance.into(),
+        weather.lightning_strike_count.into(),
+        weather.battery_voltage.into(),
+        weather.report_interval.into(),
+        weather.serial_number.into(),
+        weather.hub_sn.into(),
+    ])?;
+
+    insert_if_new(statement).await
+}
+
+async fn handle_get_device_status_latest(db: &D1Database) -> Result<Response> {
+    let status_result = db
+        .prepare("SELECT * FROM device_status ORDER BY id DESC LIMIT 1")
+        .first::<DeviceStatus>(None)
         .await?;
 
-    Response::ok("")
+    match status_result {
+        Some(status) => Response::from_json(&DeviceStatusResponse {
+            sensor_flags: status.get_sensor_flags(),
+            status,
+        }),
+        None => Response::error("No device status reports found.", 404),
+    }
+}
+
+async fn handle_post_device_status(mut req: Request, db: &D1Database) -> Result<Response> {
+    let status: DeviceStatus = req.json().await?;
+
+    // D1 doesn't accept BigInts, so 64-bit integers are bound as plain numbers.
+    let statement = db.prepare(QUERY_INSERT_DEVICE_STATUS).bind(&[
+        (status.time_epoch as f64).into(),
+        status.serial_number.into(),
+        status.hub_sn.into(),
+        (status.uptime as f64).into(),
+        status.voltage.into(),
+        (status.firmware_revision as f64).into(),
+        (status.rssi as f64).into(),
+        (status.hub_rssi as f64).into(),
+        (status.sensor_status as f64).into(),
+        status.debug.into(),
+    ])?;
+
+    insert_if_new(statement).await
 }
 
 #[event(fetch)]
@@ -48,10 +391,22 @@ async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     console_error_panic_hook::set_once();
 
     let db = env.d1("DB")?;
+    let station = station_config(&env);
+
+    if !MIGRATED.load(Ordering::Relaxed) {
+        migrate(&db).await?;
+        MIGRATED.store(true, Ordering::Relaxed);
+    }
 
     match (req.method(), &*req.path()) {
-        (Method::Get, "/weather/latest") => handle_get_weather_latest(&db).await,
+        (Method::Get, "/weather") => handle_get_weather(req, &db, station.as_ref()).await,
+        (Method::Get, "/weather/latest") => {
+            handle_get_weather_latest(req, &db, station.as_ref()).await
+        }
+        (Method::Get, "/rain") => handle_get_rain(req, &db, station.as_ref()).await,
         (Method::Post, "/weather") => handle_post_weather(req, &db).await,
+        (Method::Get, "/device_status/latest") => handle_get_device_status_latest(&db).await,
+        (Method::Post, "/device_status") => handle_post_device_status(req, &db).await,
         _ => Response::error("Not found", 404),
     }
 }